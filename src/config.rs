@@ -0,0 +1,118 @@
+use serde_json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::path::PathBuf;
+
+use discovery;
+use error::{Error, Result};
+use game::{Game, Platform};
+
+const CONFIG_DIR: &'static str = "frangiclave";
+const CONFIG_FILE: &'static str = "config.json";
+
+/// The user's settings, kept in a JSON file in their configuration directory.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Config {
+    /// Installations of the game that can't be discovered automatically.
+    #[serde(default)]
+    pub known_installs: Vec<PathBuf>,
+    /// Installations of the game registered under a name.
+    #[serde(default)]
+    pub installs: BTreeMap<String, Install>,
+    /// The name of the installation to use when none is specified.
+    #[serde(default)]
+    pub default_install: Option<String>,
+}
+
+/// An installation of the game registered under a name.
+#[derive(Serialize, Deserialize)]
+pub struct Install {
+    pub path: PathBuf,
+    pub platform: Platform,
+}
+
+impl Install {
+    /// Loads the game files of this installation.
+    pub fn game(&self) -> Game {
+        Game::with_platform(&self.path, self.platform)
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, or returns the default configuration if there is none.
+    pub fn load() -> Result<Config> {
+        let path = match Config::path() {
+            Some(p) => p,
+            None => return Ok(Config::default()),
+        };
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let file = File::open(&path).map_err(Error::io(&path))?;
+        serde_json::from_reader(file).map_err(Error::json(&path))
+    }
+
+    /// Writes the configuration file, creating its directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = match Config::path() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(Error::io(dir))?;
+        }
+        let file = File::create(&path).map_err(Error::io(&path))?;
+        serde_json::to_writer_pretty(file, self).map_err(Error::json(&path))
+    }
+
+    /// Registers an installation under `name`, replacing any previous one. The first installation
+    /// to be registered becomes the default one.
+    pub fn add_install(&mut self, name: &str, install: Install) {
+        self.installs.insert(name.to_string(), install);
+        if self.default_install.is_none() {
+            self.default_install = Some(name.to_string());
+        }
+    }
+
+    /// Unregisters an installation, returning whether it was registered.
+    pub fn remove_install(&mut self, name: &str) -> bool {
+        if self.default_install.iter().any(|d| d == name) {
+            self.default_install = None;
+        }
+        self.installs.remove(name).is_some()
+    }
+
+    /// Makes a registered installation the default one, returning whether it was registered.
+    pub fn set_default_install(&mut self, name: &str) -> bool {
+        if self.installs.contains_key(name) {
+            self.default_install = Some(name.to_string());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the default installation, if any.
+    pub fn get_default_install(&self) -> Option<&Install> {
+        self.default_install
+            .as_ref()
+            .and_then(|name| self.installs.get(name))
+    }
+
+    /// Returns the location of the configuration file for the current user.
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = if cfg!(target_os = "windows") {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            discovery::home_dir().map(|h| h.join("Library/Application Support"))
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .filter(|d| !d.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| discovery::home_dir().map(|h| h.join(".config")))
+        };
+        config_dir.map(|d| d.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+}
@@ -0,0 +1,477 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use game::Game;
+
+const CULTIST_SIMULATOR_APP_ID: &'static str = "718670";
+const STEAM_ROOT_VARIABLE: &'static str = "STEAM_ROOT";
+
+const WINDOWS_STEAM_ROOTS: [&'static str; 2] =
+    ["C:\\Program Files (x86)\\Steam", "C:\\Program Files\\Steam"];
+const MACOS_STEAM_ROOTS: [&'static str; 1] = ["Library/Application Support/Steam"];
+const LINUX_STEAM_ROOTS: [&'static str; 3] = [
+    ".steam/steam",
+    ".local/share/Steam",
+    ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+];
+
+const WINDOWS_STANDALONE_INSTALLS: [&'static str; 4] = [
+    "C:\\GOG Games\\Cultist Simulator",
+    "C:\\Program Files (x86)\\GOG Galaxy\\Games\\Cultist Simulator",
+    "C:\\Program Files (x86)\\GOG Games\\Cultist Simulator",
+    "C:\\Program Files (x86)\\Cultist Simulator",
+];
+const MACOS_STANDALONE_INSTALLS: [&'static str; 1] = ["/Applications/Cultist Simulator.app"];
+const HOME_STANDALONE_INSTALLS: [&'static str; 3] = [
+    "GOG Games/Cultist Simulator",
+    "GOG Games/Cultist Simulator/game",
+    "Applications/Cultist Simulator.app",
+];
+
+/// A value in one of Steam's VDF (KeyValues) files.
+#[derive(Debug, PartialEq)]
+pub enum VdfValue {
+    String(String),
+    Object(Vec<(String, VdfValue)>),
+}
+
+impl VdfValue {
+    /// Returns the value for `key`, if this is an object containing it.
+    pub fn get(&self, key: &str) -> Option<&VdfValue> {
+        match self {
+            VdfValue::Object(entries) => entries
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            VdfValue::String(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(s) => Some(s),
+            VdfValue::Object(_) => None,
+        }
+    }
+}
+
+/// Finds every installation of Cultist Simulator on this system, from Steam, from the usual
+/// standalone (GOG, Humble) locations, and from `known_installs`.
+pub fn find_installs(known_installs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut installs = Vec::new();
+    let candidates = find_steam_installs()
+        .into_iter()
+        .chain(standalone_install_candidates())
+        .chain(known_installs.iter().cloned());
+    for candidate in candidates {
+        let install = fs::canonicalize(&candidate).unwrap_or(candidate);
+        if !installs.contains(&install) && Game::new(&install).is_valid() {
+            installs.push(install);
+        }
+    }
+    installs
+}
+
+/// Returns the usual locations of standalone installations of Cultist Simulator on this system.
+pub fn standalone_install_candidates() -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = if cfg!(target_os = "windows") {
+        WINDOWS_STANDALONE_INSTALLS.iter().map(PathBuf::from).collect()
+    } else if cfg!(target_os = "macos") {
+        MACOS_STANDALONE_INSTALLS.iter().map(PathBuf::from).collect()
+    } else {
+        Vec::new()
+    };
+    if let Some(home) = home_dir() {
+        candidates.extend(HOME_STANDALONE_INSTALLS.iter().map(|i| home.join(i)));
+    }
+    candidates
+}
+
+/// Finds every Steam installation of Cultist Simulator on this system.
+pub fn find_steam_installs() -> Vec<PathBuf> {
+    let mut installs = Vec::new();
+    for steam_root in steam_roots() {
+        for install in find_steam_installs_in(&steam_root) {
+            if !installs.contains(&install) {
+                installs.push(install);
+            }
+        }
+    }
+    installs
+}
+
+/// Returns the root directories of the Steam installations on this system, or only the one set
+/// in the `STEAM_ROOT` environment variable.
+pub fn steam_roots() -> Vec<PathBuf> {
+    if let Some(steam_root) = env::var_os(STEAM_ROOT_VARIABLE) {
+        return vec![PathBuf::from(steam_root)];
+    }
+    let candidates: Vec<PathBuf> = if cfg!(target_os = "windows") {
+        WINDOWS_STEAM_ROOTS.iter().map(PathBuf::from).collect()
+    } else {
+        let home = match home_dir() {
+            Some(h) => h,
+            None => return Vec::new(),
+        };
+        let roots: &[&str] = if cfg!(target_os = "macos") {
+            &MACOS_STEAM_ROOTS
+        } else {
+            &LINUX_STEAM_ROOTS
+        };
+        roots.iter().map(|r| home.join(r)).collect()
+    };
+
+    // Several of these may be links to the same installation.
+    let mut steam_roots = Vec::new();
+    for candidate in candidates {
+        if let Ok(steam_root) = fs::canonicalize(&candidate) {
+            if steam_root.is_dir() && !steam_roots.contains(&steam_root) {
+                steam_roots.push(steam_root);
+            }
+        }
+    }
+    steam_roots
+}
+
+/// Finds every installation of Cultist Simulator in the libraries of the Steam installation at
+/// `steam_root`.
+pub fn find_steam_installs_in(steam_root: &Path) -> Vec<PathBuf> {
+    let mut installs = Vec::new();
+    for library in steam_libraries(steam_root) {
+        let steamapps = library.join("steamapps");
+        let app_manifest_path =
+            steamapps.join(format!("appmanifest_{}.acf", CULTIST_SIMULATOR_APP_ID));
+        let install_dir = match read_vdf(&app_manifest_path) {
+            Some(app_manifest) => match app_manifest
+                .get("AppState")
+                .and_then(|a| a.get("installdir"))
+                .and_then(|d| d.as_str())
+            {
+                Some(d) => d.to_string(),
+                None => continue,
+            },
+            None => continue,
+        };
+        let install = steamapps.join("common").join(install_dir);
+        if install.is_dir() {
+            installs.push(install);
+        }
+    }
+    installs
+}
+
+/// Returns the Steam library folders of the Steam installation at `steam_root`, starting with the
+/// installation itself.
+pub fn steam_libraries(steam_root: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![steam_root.to_path_buf()];
+
+    // Older versions of Steam keep the list of libraries with the games, newer ones with the
+    // configuration.
+    let library_folders = read_vdf(&steam_root.join("steamapps").join("libraryfolders.vdf"))
+        .or_else(|| read_vdf(&steam_root.join("config").join("libraryfolders.vdf")));
+    let library_folders = match library_folders
+        .as_ref()
+        .and_then(|f| f.get("libraryfolders"))
+    {
+        Some(VdfValue::Object(entries)) => entries,
+        _ => return libraries,
+    };
+
+    // Libraries are numbered, and either a path, or an object containing the path.
+    for (key, value) in library_folders {
+        if !key.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let path = match value {
+            VdfValue::String(path) => Some(path.as_str()),
+            VdfValue::Object(_) => value.get("path").and_then(|p| p.as_str()),
+        };
+        if let Some(path) = path {
+            let library = PathBuf::from(path);
+            if !libraries.contains(&library) {
+                libraries.push(library);
+            }
+        }
+    }
+    libraries
+}
+
+fn read_vdf(path: &Path) -> Option<VdfValue> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| parse_vdf(&contents))
+}
+
+/// Parses the contents of a VDF file, returning its top-level keys as an object.
+pub fn parse_vdf(contents: &str) -> Option<VdfValue> {
+    let tokens = tokenize_vdf(contents)?;
+    let mut position = 0;
+    let entries = parse_vdf_entries(&tokens, &mut position)?;
+    if position == tokens.len() {
+        Some(VdfValue::Object(entries))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum VdfToken {
+    String(String),
+    Open,
+    Close,
+}
+
+fn tokenize_vdf(contents: &str) -> Option<Vec<VdfToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => tokens.push(VdfToken::Open),
+            '}' => tokens.push(VdfToken::Close),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => s.push('\n'),
+                            't' => s.push('\t'),
+                            other => s.push(other),
+                        },
+                        other => s.push(other),
+                    }
+                }
+                tokens.push(VdfToken::String(s));
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                // Skip comments until the end of the line
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                // Unquoted strings end at the next whitespace or brace
+                let mut s = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '{' || next == '}' || next == '"' {
+                        break;
+                    }
+                    s.push(next);
+                    chars.next();
+                }
+                tokens.push(VdfToken::String(s));
+            }
+        }
+    }
+    Some(tokens)
+}
+
+fn parse_vdf_entries(tokens: &[VdfToken], position: &mut usize) -> Option<Vec<(String, VdfValue)>> {
+    let mut entries = Vec::new();
+    while *position < tokens.len() {
+        let key = match &tokens[*position] {
+            VdfToken::String(key) => key.clone(),
+            VdfToken::Close => break,
+            VdfToken::Open => return None,
+        };
+        *position += 1;
+        let value = match tokens.get(*position)? {
+            VdfToken::String(value) => {
+                *position += 1;
+                VdfValue::String(value.clone())
+            }
+            VdfToken::Open => {
+                *position += 1;
+                let object = parse_vdf_entries(tokens, position)?;
+                match tokens.get(*position)? {
+                    VdfToken::Close => *position += 1,
+                    _ => return None,
+                }
+                VdfValue::Object(object)
+            }
+            VdfToken::Close => return None,
+        };
+        entries.push((key, value));
+    }
+    Some(entries)
+}
+
+/// Returns the home directory of the current user.
+pub fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/steam")
+            .join(name);
+        fs::read_to_string(&path).unwrap()
+    }
+
+    fn write_file(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(path)
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn parse_old_library_folders() {
+        let vdf = parse_vdf(&fixture("libraryfolders_old.vdf")).unwrap();
+        let folders = vdf.get("libraryfolders").unwrap();
+        assert_eq!(
+            folders.get("1").and_then(|p| p.as_str()),
+            Some("D:\\SteamLibrary")
+        );
+        assert_eq!(
+            folders.get("2").and_then(|p| p.as_str()),
+            Some("/mnt/games/SteamLibrary")
+        );
+    }
+
+    #[test]
+    fn parse_new_library_folders() {
+        let vdf = parse_vdf(&fixture("libraryfolders_new.vdf")).unwrap();
+        let library = vdf.get("libraryfolders").and_then(|f| f.get("1")).unwrap();
+        assert_eq!(
+            library.get("path").and_then(|p| p.as_str()),
+            Some("D:\\Games\\SteamLibrary")
+        );
+        assert_eq!(
+            library
+                .get("apps")
+                .and_then(|a| a.get("718670"))
+                .and_then(|s| s.as_str()),
+            Some("715827882")
+        );
+    }
+
+    #[test]
+    fn parse_app_manifest() {
+        let vdf = parse_vdf(&fixture("appmanifest_718670.acf")).unwrap();
+        let app_state = vdf.get("AppState").unwrap();
+        assert_eq!(
+            app_state.get("installdir").and_then(|d| d.as_str()),
+            Some("Cultist Simulator")
+        );
+        assert_eq!(
+            app_state
+                .get("UserConfig")
+                .and_then(|c| c.get("language"))
+                .and_then(|l| l.as_str()),
+            Some("english")
+        );
+    }
+
+    #[test]
+    fn parse_escapes_and_comments() {
+        let vdf = parse_vdf("// comment\n\"key\" \"a \\\"quoted\\\" \\\\ value\\n\"").unwrap();
+        assert_eq!(
+            vdf.get("key").and_then(|v| v.as_str()),
+            Some("a \"quoted\" \\ value\n")
+        );
+    }
+
+    #[test]
+    fn parse_malformed_vdf() {
+        // Unbalanced braces
+        assert_eq!(parse_vdf("\"a\" { \"b\" \"c\""), None);
+        assert_eq!(parse_vdf("\"a\" { \"b\" \"c\" } }"), None);
+        assert_eq!(parse_vdf("{ \"b\" \"c\" }"), None);
+        // Unterminated strings
+        assert_eq!(parse_vdf("\"a\" \"b"), None);
+        assert_eq!(parse_vdf("\"a\" \"b\\"), None);
+        // Key without a value
+        assert_eq!(parse_vdf("\"a\" { \"b\" }"), None);
+    }
+
+    #[test]
+    fn steam_libraries_old_format() {
+        let steam_root = TempDir::new("frangiclave-steam").unwrap();
+        write_file(
+            &steam_root.path().join("steamapps/libraryfolders.vdf"),
+            &fixture("libraryfolders_old.vdf"),
+        );
+        assert_eq!(
+            steam_libraries(steam_root.path()),
+            vec![
+                steam_root.path().to_path_buf(),
+                PathBuf::from("D:\\SteamLibrary"),
+                PathBuf::from("/mnt/games/SteamLibrary"),
+            ]
+        );
+    }
+
+    #[test]
+    fn steam_libraries_new_format() {
+        let steam_root = TempDir::new("frangiclave-steam").unwrap();
+        write_file(
+            &steam_root.path().join("config/libraryfolders.vdf"),
+            &fixture("libraryfolders_new.vdf"),
+        );
+        assert_eq!(
+            steam_libraries(steam_root.path()),
+            vec![
+                steam_root.path().to_path_buf(),
+                PathBuf::from("C:\\Program Files (x86)\\Steam"),
+                PathBuf::from("D:\\Games\\SteamLibrary"),
+            ]
+        );
+    }
+
+    #[test]
+    fn steam_libraries_without_library_folders() {
+        let steam_root = TempDir::new("frangiclave-steam").unwrap();
+        write_file(&steam_root.path().join("steamapps/libraryfolders.vdf"), "\"a\" {");
+        assert_eq!(
+            steam_libraries(steam_root.path()),
+            vec![steam_root.path().to_path_buf()]
+        );
+    }
+
+    #[test]
+    fn find_installs_in_libraries() {
+        let steam_root = TempDir::new("frangiclave-steam").unwrap();
+        let library = TempDir::new("frangiclave-library").unwrap();
+        let empty_library = TempDir::new("frangiclave-library").unwrap();
+        let library_folders = format!(
+            "\"libraryfolders\" {{ \"1\" {{ \"path\" \"{}\" }} \"2\" \"{}\" }}",
+            library.path().display().to_string().replace('\\', "\\\\"),
+            empty_library.path().display().to_string().replace('\\', "\\\\")
+        );
+        write_file(
+            &steam_root.path().join("steamapps/libraryfolders.vdf"),
+            &library_folders,
+        );
+
+        // The game is only installed in the second library, the first one only has its manifest.
+        let app_manifest = fixture("appmanifest_718670.acf");
+        write_file(
+            &steam_root.path().join("steamapps/appmanifest_718670.acf"),
+            &app_manifest,
+        );
+        write_file(
+            &library.path().join("steamapps/appmanifest_718670.acf"),
+            &app_manifest,
+        );
+        let install = library.path().join("steamapps/common/Cultist Simulator");
+        fs::create_dir_all(&install).unwrap();
+
+        assert_eq!(find_steam_installs_in(steam_root.path()), vec![install]);
+    }
+}
@@ -0,0 +1,229 @@
+use reqwest;
+use reqwest::StatusCode;
+use serde_json;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result;
+use zip::result::ZipError;
+
+use semver::Version;
+
+use game::ManifestError;
+
+/// A result whose error is a frangiclave `Error`.
+pub type Result<T> = result::Result<T, Error>;
+
+/// An error encountered while managing the game or its mods.
+#[derive(Debug)]
+pub enum Error {
+    /// A request to the mod repository could not be completed.
+    Network { url: String, source: reqwest::Error },
+    /// The mod repository answered a request with an unexpected status.
+    HttpStatus { url: String, status: StatusCode },
+    /// The mod repository answered a request with data that couldn't be understood.
+    InvalidResponse { url: String, reason: String },
+    /// A downloaded mod archive could not be extracted.
+    Zip { path: PathBuf, source: ZipError },
+    /// An installed or downloaded mod has a missing or invalid manifest.
+    Manifest(ManifestError),
+    /// A dependency specifier could not be parsed.
+    InvalidDependency { specifier: String, reason: String },
+    /// A version requirement could not be parsed.
+    InvalidVersionRequirement { requirement: String, reason: String },
+    /// No acceptable version could be found for a mod.
+    VersionResolution { mod_id: String, reason: String },
+    /// A mod that was expected to be installed isn't.
+    NotInstalled { mod_id: String },
+    /// A mod can't be removed or disabled because other installed mods depend on it.
+    HasDependents {
+        mod_id: String,
+        dependents: Vec<String>,
+    },
+    /// A mod recorded in the lockfile can't be downloaded, as it wasn't installed from a
+    /// repository.
+    MissingSource { mod_id: String, version: Version },
+    /// A downloaded mod archive isn't the one recorded in the lockfile.
+    ChecksumMismatch {
+        mod_id: String,
+        expected: String,
+        actual: String,
+    },
+    /// A mod doesn't support the installed version of the game.
+    IncompatibleGameVersion {
+        mod_id: String,
+        requirement: String,
+        game_version: Option<Version>,
+    },
+    /// A mod can't be installed along with another one.
+    IncompatibleMods {
+        mod_id: String,
+        version: String,
+        other_id: String,
+        other_version: String,
+    },
+    /// The game's version file contains a version that couldn't be understood.
+    GameVersion { path: PathBuf, version: String },
+    /// Mods that depend on each other in a cycle require versions that can't be satisfied.
+    DependencyCycle { cycle: Vec<String>, reason: String },
+    /// MonoMod ran, but failed to patch the game assembly.
+    MonoMod { status: Option<i32>, stderr: String },
+    /// Files couldn't be moved into place, and putting back the files they replaced failed too;
+    /// the next transaction on the same directory will try again.
+    Rollback {
+        source: Box<Error>,
+        rollback_error: Box<Error>,
+    },
+    /// A JSON file, such as the configuration or the lockfile, is malformed.
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A filesystem operation failed.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl Error {
+    /// Returns a function wrapping an I/O error that occurred while operating on `path`, to be
+    /// used with `map_err`.
+    pub(crate) fn io<P: AsRef<Path>>(path: P) -> impl FnOnce(io::Error) -> Error {
+        let path = path.as_ref().to_path_buf();
+        move |source| Error::Io { path, source }
+    }
+
+    /// Returns a function wrapping a JSON error that occurred while reading or writing `path`, to
+    /// be used with `map_err`. Errors accessing the file itself are reported as I/O errors.
+    pub(crate) fn json<P: AsRef<Path>>(path: P) -> impl FnOnce(serde_json::Error) -> Error {
+        let path = path.as_ref().to_path_buf();
+        move |source| {
+            if source.is_io() {
+                Error::Io {
+                    path,
+                    source: source.into(),
+                }
+            } else {
+                Error::Json { path, source }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Network { url, source } => write!(f, "Failed to fetch '{}': {}", url, source),
+            Error::HttpStatus { url, status } => {
+                write!(f, "Failed to fetch '{}': server returned {}", url, status)
+            }
+            Error::InvalidResponse { url, reason } => {
+                write!(f, "Invalid response from '{}': {}", url, reason)
+            }
+            Error::Zip { path, source } => {
+                write!(f, "Failed to extract '{}': {}", path.display(), source)
+            }
+            Error::Manifest(e) => write!(f, "{}", e),
+            Error::InvalidDependency { specifier, reason } => {
+                write!(f, "Invalid dependency specifier '{}': {}", specifier, reason)
+            }
+            Error::InvalidVersionRequirement {
+                requirement,
+                reason,
+            } => write!(f, "Invalid version requirement '{}': {}", requirement, reason),
+            Error::VersionResolution { mod_id, reason } => {
+                write!(f, "Failed to resolve a version of mod '{}': {}", mod_id, reason)
+            }
+            Error::NotInstalled { mod_id } => write!(f, "Mod '{}' is not installed", mod_id),
+            Error::HasDependents { mod_id, dependents } => write!(
+                f,
+                "Mod '{}' is required by {}",
+                mod_id,
+                dependents.join(", ")
+            ),
+            Error::MissingSource { mod_id, version } => write!(
+                f,
+                "Mod '{}' {} is locked, but it wasn't installed from a repository",
+                mod_id, version
+            ),
+            Error::ChecksumMismatch {
+                mod_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch for mod '{}': expected {}, got {}",
+                mod_id, expected, actual
+            ),
+            Error::IncompatibleGameVersion {
+                mod_id,
+                requirement,
+                game_version,
+            } => {
+                write!(f, "Mod '{}' requires game version {}, ", mod_id, requirement)?;
+                match game_version {
+                    Some(version) => write!(f, "but the game is version {}", version),
+                    None => write!(f, "but the game version could not be determined"),
+                }
+            }
+            Error::IncompatibleMods {
+                mod_id,
+                version,
+                other_id,
+                other_version,
+            } => write!(
+                f,
+                "Mod '{}' {} is incompatible with mod '{}' {}",
+                mod_id, version, other_id, other_version
+            ),
+            Error::GameVersion { path, version } => write!(
+                f,
+                "Unrecognized game version '{}' in '{}'",
+                version,
+                path.display()
+            ),
+            Error::DependencyCycle { cycle, reason } => write!(
+                f,
+                "Failed to resolve the dependency cycle {}: {}",
+                cycle.join(" -> "),
+                reason
+            ),
+            Error::MonoMod { status, stderr } => {
+                match status {
+                    Some(code) => write!(f, "MonoMod exited with status {}", code)?,
+                    None => write!(f, "MonoMod was terminated")?,
+                }
+                write!(f, " while patching Assembly-CSharp.dll: {}", stderr.trim())
+            }
+            Error::Rollback {
+                source,
+                rollback_error,
+            } => write!(
+                f,
+                "{} (rolling back also failed: {})",
+                source, rollback_error
+            ),
+            Error::Json { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Network { source, .. } => Some(source),
+            Error::Zip { source, .. } => Some(source),
+            Error::Manifest(e) => Some(e),
+            Error::Rollback { source, .. } => Some(source.as_ref()),
+            Error::Json { source, .. } => Some(source),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ManifestError> for Error {
+    fn from(e: ManifestError) -> Error {
+        Error::Manifest(e)
+    }
+}
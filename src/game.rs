@@ -1,13 +1,23 @@
+use error::{Error, Result};
+use lockfile::{InstallReason, Lockfile};
 use patch;
-use regex::Regex;
-use semver::Version;
+use regex::{Captures, Regex};
+use resolver;
+use semver::{Version, VersionReq};
 use serde_json;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::result;
 use std::str;
+use transaction;
+use transaction::Transaction;
 
 const WINDOWS_EXE_PATH: &'static str = "cultistsimulator.exe";
 const MACOS_EXE_PATH: &'static str = "Contents/MacOS/OSX";
@@ -19,148 +29,767 @@ const LINUX_DATA_PATH: &'static str = "CS_Data/";
 const MANAGED_PATH: &'static str = "Managed/";
 const ASSEMBLY_PATH: &'static str = "Managed/Assembly-CSharp.dll";
 const ASSEMBLY_BACKUP_PATH: &'static str = "Managed/Assembly-CSharp-backup.dll";
+const OLD_ASSEMBLY_BACKUP_PREFIX: &'static str = "Assembly-CSharp-backup-";
+const PATCH_RECORD_PATH: &'static str = "Managed/FrangiclavePatch.json";
 const MODS_PATH: &'static str = "StreamingAssets/mods";
+const DISABLED_MODS_PATH: &'static str = "StreamingAssets/mods-disabled";
+const VERSION_PATH: &'static str = "StreamingAssets/version.txt";
+const MANIFEST_PATH: &'static str = "manifest.json";
 
-const MOD_DEPENDENCY_VERSION: &'static str = r"^\s*(\w+)(?:\s*(<=|<|>=|>|==)\s*([\d.]+))?\s*$";
+const MOD_DEPENDENCY: &'static str = r"^\s*(\w[\w.-]*)\s*([<>=^~*\d].*?)?\s*$";
+const MOD_ID: &'static str = r"^\w[\w.-]*$";
 
+const GAME_VERSION: &'static str = r"\b\d+\.\d+\.[A-Za-z](\.\d+)?\b";
+
+// Number of hotfixes each release of the game can have, used to fold them into the patch number.
+const HOTFIXES_PER_RELEASE: u64 = 100;
+
+// MonoMod adds this type to every assembly it patches.
+const MONOMOD_MARKER: &'static [u8] = b"WasHere\0";
+
+/// An installation of Cultist Simulator.
 pub struct Game {
+    root: PathBuf,
+    platform: Platform,
     exe_path: PathBuf,
     managed_path: PathBuf,
     assembly_path: PathBuf,
     assembly_backup_path: PathBuf,
+    patch_record_path: PathBuf,
     mods_path: PathBuf,
+    disabled_mods_path: PathBuf,
+    version_path: PathBuf,
+}
+
+/// The platform a build of the game was made for, which determines the layout of its files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Windows,
+    MacOs,
+    Linux,
+}
+
+/// Whether, and how, the game assembly is currently patched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatchStatus {
+    /// The game assembly is not patched.
+    Vanilla,
+    /// The game assembly is patched with the patch bundled with this program.
+    Patched,
+    /// The game assembly is patched, but with an older or unknown version of the patch.
+    PatchOutdated,
+    /// The game assembly was replaced by a game update since it was patched.
+    GameUpdatedSinceBackup,
+}
+
+/// Fingerprints of the files involved in the last successful patch.
+#[derive(Serialize, Deserialize)]
+struct PatchRecord {
+    patch_hash: String,
+    backup_hash: String,
+    patched_hash: String,
 }
 
 impl Game {
+    /// Locates the game files inside the `root` directory of an installation, whichever platform
+    /// it was built for.
     pub fn new(root: &PathBuf) -> Game {
-        let exe_path;
-        let data_path;
-        if cfg!(all(target_os = "windows", target_arch = "x86_64")) {
-            exe_path = root.join(WINDOWS_EXE_PATH);
-            data_path = root.join(WINDOWS_DATA_PATH);
-        } else if cfg!(all(target_os = "macos", target_arch = "x86_64")) {
-            exe_path = root.join(MACOS_EXE_PATH);
-            data_path = root.join(MACOS_DATA_PATH);
-        } else if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
-            exe_path = root.join(LINUX_EXE_PATH);
-            data_path = root.join(LINUX_DATA_PATH);
-        } else {
-            panic!("Invalid target os or architecture");
-        }
+        let platform = Platform::detect(root).unwrap_or_else(Platform::host);
+        Game::with_platform(root, platform)
+    }
+
+    /// Locates the game files inside the `root` directory of an installation built for
+    /// `platform`.
+    pub fn with_platform(root: &PathBuf, platform: Platform) -> Game {
+        let exe_path = root.join(platform.exe_path());
+        let data_path = root.join(platform.data_path());
         Game {
+            root: root.clone(),
+            platform,
             exe_path,
             managed_path: data_path.join(MANAGED_PATH),
             assembly_path: data_path.join(ASSEMBLY_PATH),
             assembly_backup_path: data_path.join(ASSEMBLY_BACKUP_PATH),
+            patch_record_path: data_path.join(PATCH_RECORD_PATH),
             mods_path: data_path.join(MODS_PATH),
+            disabled_mods_path: data_path.join(DISABLED_MODS_PATH),
+            version_path: data_path.join(VERSION_PATH),
         }
     }
 
+    /// Returns the root directory of the installation.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the platform the game was built for.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Returns whether the game files were actually found.
     pub fn is_valid(&self) -> bool {
         self.exe_path.is_file() && self.assembly_path.is_file()
     }
 
-    pub fn patch_assembly(&self) -> Result<(), String> {
-        // If no backup assembly exists, create one, then use the backup assembly as a basis for the
-        // patch.
-        // This is to prevent double-patching the assembly.
-        if !self.assembly_backup_path.is_file() {
-            match fs::copy(&self.assembly_path, &self.assembly_backup_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    return Err(format!(
-                        "Failed to copy {}: {}",
-                        &self.assembly_path.display(),
-                        e
-                    ))
-                }
-            }
+    /// Reads the version of the game from the version file it ships with, if there is one.
+    ///
+    /// The game is versioned as `YEAR.MONTH.LETTER.HOTFIX` (e.g. `2019.1.b.2`), which is read as
+    /// `YEAR.MONTH.(N * 100 + HOTFIX)`, `N` being the position of the letter in the alphabet
+    /// starting from 0, so that `2019.1.b.2` becomes `2019.1.102`.
+    pub fn version(&self) -> Result<Option<Version>> {
+        if !self.version_path.is_file() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read_to_string(&self.version_path).map_err(Error::io(&self.version_path))?;
+        match parse_game_version(contents.trim()) {
+            Some(version) => Ok(Some(version)),
+            None => Err(Error::GameVersion {
+                path: self.version_path.clone(),
+                version: contents.trim().to_string(),
+            }),
         }
-        let dir = match patch::setup_patch_directory(&self.managed_path) {
-            Ok(d) => d,
-            Err(e) => return Err(format!("Failed to set up temporary patch directory: {}", e)),
+    }
+
+    /// Checks that a mod supports the installed version of the game.
+    pub fn check_game_version(&self, checked_mod: &Mod) -> Result<()> {
+        let requirement = match &checked_mod.game_version {
+            Some(r) => r,
+            None => return Ok(()),
         };
+        let game_version = self.version()?;
+        match &game_version {
+            Some(version) if requirement.matches(version) => Ok(()),
+            _ => Err(Error::IncompatibleGameVersion {
+                mod_id: checked_mod.id.clone(),
+                requirement: requirement.to_string(),
+                game_version,
+            }),
+        }
+    }
+
+    /// Patches the game assembly with the bundled FrangiclavePatch, so that it loads mods.
+    pub fn patch_assembly(&self) -> Result<()> {
+        // If no up-to-date backup assembly exists, create one, then use the backup assembly as a
+        // basis for the patch.
+        // This is to prevent double-patching the assembly.
+        self.refresh_assembly_backup()?;
+        let dir = patch::setup_patch_directory(&self.managed_path, &self.assembly_backup_path)?;
 
         // Run MonoMod to patch the clean assembly.
+        let monomod_path = dir.path().join("MonoMod.exe");
         let command = if cfg!(unix) {
             Command::new("mono")
-                .arg(dir.path().join("MonoMod.exe"))
+                .arg(&monomod_path)
                 .arg("--dependency-missing-throw=0")
                 .arg("Assembly-CSharp.dll")
                 .current_dir(dir.path())
                 .output()
         } else {
-            Command::new(dir.path().join("MonoMod.exe"))
+            Command::new(&monomod_path)
                 .arg("--dependency-missing-throw=0")
                 .arg("Assembly-CSharp.dll")
                 .current_dir(dir.path())
                 .output()
         };
-
-        match command {
-            Ok(output) => {
-                if !output.status.success() {
-                    return Err(format!(
-                        "MonoMod failed to patch Assembly-CSharp.dll: {}",
-                        str::from_utf8(&output.stderr).unwrap()
-                    ));
-                }
-            }
-            Err(e) => return Err(format!("Failed to start MonoMod: {}", e)),
+        let output = command.map_err(Error::io(&monomod_path))?;
+        if !output.status.success() {
+            return Err(Error::MonoMod {
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
         }
 
         // Prepare the patch directory for copy back to the game directory.
         let pdb_path = dir.path().join("MONOMODDED_Assembly-CSharp.pdb");
         let mdb_path = dir.path().join("MONOMODDED_Assembly-CSharp.mdb");
         if pdb_path.exists() {
-            fs::remove_file(pdb_path).unwrap();
+            fs::remove_file(&pdb_path).map_err(Error::io(&pdb_path))?;
         }
         if mdb_path.exists() {
-            fs::remove_file(mdb_path).unwrap();
+            fs::remove_file(&mdb_path).map_err(Error::io(&mdb_path))?;
         }
-        fs::remove_file(dir.path().join("Assembly-CSharp.FrangiclavePatch.mm.dll")).unwrap();
-        fs::rename(
-            dir.path().join("MONOMODDED_Assembly-CSharp.dll"),
-            dir.path().join("Assembly-CSharp.dll"),
-        ).unwrap();
+        let patch_path = dir.path().join("Assembly-CSharp.FrangiclavePatch.mm.dll");
+        fs::remove_file(&patch_path).map_err(Error::io(&patch_path))?;
+        let patched_assembly_path = dir.path().join("MONOMODDED_Assembly-CSharp.dll");
+        fs::rename(&patched_assembly_path, dir.path().join("Assembly-CSharp.dll"))
+            .map_err(Error::io(&patched_assembly_path))?;
+
+        // Remember what was patched, so that the patch status can be checked later on.
+        let record = PatchRecord {
+            patch_hash: patch::patch_hash(),
+            backup_hash: patch::hash_file(&self.assembly_backup_path)?,
+            patched_hash: patch::hash_file(&dir.path().join("Assembly-CSharp.dll"))?,
+        };
+        let record_path = dir.path().join(self.patch_record_path.file_name().unwrap());
+        let record_file = File::create(&record_path).map_err(Error::io(&record_path))?;
+        serde_json::to_writer_pretty(record_file, &record).map_err(Error::json(&record_path))?;
 
         // Copy every file back, as the patched assembly will need MonoMod and its dependencies.
-        for dir_entry in fs::read_dir(dir.path()).unwrap() {
-            let path = &dir_entry.unwrap().path();
-            let file_name = path.file_name().unwrap().to_str().unwrap();
-            match fs::copy(path, self.managed_path.join(file_name)) {
-                Ok(_) => (),
-                Err(e) => return Err(format!("Failed to copy {}: {}", path.display(), e)),
+        // This is done in a single transaction, so that a failure never leaves the game
+        // half-patched.
+        let mut transaction = Transaction::begin(&self.managed_path)?;
+        for dir_entry in fs::read_dir(dir.path()).map_err(Error::io(dir.path()))? {
+            transaction.stage(&dir_entry.map_err(Error::io(dir.path()))?.path())?;
+        }
+        transaction.commit()?;
+
+        let dir_path = dir.path().to_path_buf();
+        dir.close().map_err(Error::io(dir_path))
+    }
+
+    /// Makes sure the backup assembly is a copy of the current unpatched game assembly.
+    ///
+    /// If the game was updated since the backup was made, the old backup is kept alongside the new
+    /// one, named after its fingerprint, replacing any backup kept from an earlier update.
+    fn refresh_assembly_backup(&self) -> Result<()> {
+        if self.assembly_backup_path.is_file() {
+            if self.patch_status()? != PatchStatus::GameUpdatedSinceBackup {
+                return Ok(());
             }
+            self.remove_old_assembly_backups()?;
+            let old_backup_hash = patch::hash_file(&self.assembly_backup_path)?;
+            let old_backup_path = self
+                .managed_path
+                .join(format!("{}{}.dll", OLD_ASSEMBLY_BACKUP_PREFIX, &old_backup_hash[..12]));
+            fs::rename(&self.assembly_backup_path, &old_backup_path)
+                .map_err(Error::io(&self.assembly_backup_path))?;
         }
+        fs::copy(&self.assembly_path, &self.assembly_backup_path)
+            .map_err(Error::io(&self.assembly_path))?;
+        Ok(())
+    }
 
-        dir.close().unwrap();
+    /// Deletes the backups of the game assembly kept from before earlier game updates.
+    fn remove_old_assembly_backups(&self) -> Result<()> {
+        for dir_entry in fs::read_dir(&self.managed_path).map_err(Error::io(&self.managed_path))? {
+            let path = dir_entry.map_err(Error::io(&self.managed_path))?.path();
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            if file_name.starts_with(OLD_ASSEMBLY_BACKUP_PREFIX) && file_name.ends_with(".dll") {
+                fs::remove_file(&path).map_err(Error::io(&path))?;
+            }
+        }
         Ok(())
     }
 
-    pub fn get_mod(&self, mod_id: &str) -> Option<Mod> {
+    /// Determines whether the game assembly is patched, by comparing it with the backup made before
+    /// patching and with what the last patch produced.
+    pub fn patch_status(&self) -> Result<PatchStatus> {
+        if !self.assembly_backup_path.is_file() {
+            return Ok(PatchStatus::Vanilla);
+        }
+        let mut assembly = Vec::new();
+        File::open(&self.assembly_path)
+            .and_then(|mut f| f.read_to_end(&mut assembly))
+            .map_err(Error::io(&self.assembly_path))?;
+        let assembly_hash = patch::hash_bytes(&assembly);
+        if assembly_hash == patch::hash_file(&self.assembly_backup_path)? {
+            return Ok(PatchStatus::Vanilla);
+        }
+
+        // Compare the assembly with the fingerprints recorded by the last patch: if it is neither
+        // what was backed up nor what the patch produced, the game replaced it in an update.
+        if let Some(record) = self.read_patch_record() {
+            return Ok(if record.patched_hash == assembly_hash {
+                if record.patch_hash == patch::patch_hash() {
+                    PatchStatus::Patched
+                } else {
+                    PatchStatus::PatchOutdated
+                }
+            } else if record.backup_hash == assembly_hash {
+                PatchStatus::Vanilla
+            } else {
+                PatchStatus::GameUpdatedSinceBackup
+            });
+        }
+
+        // Without a record, look for the mark MonoMod leaves in the assemblies it patches to tell
+        // whether the assembly was patched by an older version, or replaced by a game update.
+        if assembly
+            .windows(MONOMOD_MARKER.len())
+            .any(|w| w == MONOMOD_MARKER)
+        {
+            Ok(PatchStatus::PatchOutdated)
+        } else {
+            Ok(PatchStatus::GameUpdatedSinceBackup)
+        }
+    }
+
+    fn read_patch_record(&self) -> Option<PatchRecord> {
+        File::open(&self.patch_record_path)
+            .ok()
+            .and_then(|f| serde_json::from_reader(f).ok())
+    }
+
+    /// Undoes the patch, putting back the original game assembly and removing the files that
+    /// patching added, and optionally every installed mod.
+    pub fn restore_assembly(&self, remove_mods: bool) -> Result<()> {
+        transaction::recover(&self.managed_path)?;
+
+        // If the game was updated since it was patched, the current assembly is already unpatched,
+        // and the backup would only bring back old game code.
+        if self.patch_status()? == PatchStatus::GameUpdatedSinceBackup {
+            fs::remove_file(&self.assembly_backup_path)
+                .map_err(Error::io(&self.assembly_backup_path))?;
+        } else if self.assembly_backup_path.is_file() {
+            fs::rename(&self.assembly_backup_path, &self.assembly_path)
+                .map_err(Error::io(&self.assembly_backup_path))?;
+        }
+        if self.patch_record_path.exists() {
+            fs::remove_file(&self.patch_record_path).map_err(Error::io(&self.patch_record_path))?;
+        }
+        self.remove_old_assembly_backups()?;
+        for file_name in patch::SUPPORT_FILES.iter() {
+            let path = self.managed_path.join(file_name);
+            if path.exists() {
+                fs::remove_file(&path).map_err(Error::io(&path))?;
+            }
+        }
+        if remove_mods {
+            for path in [&self.mods_path, &self.disabled_mods_path].iter() {
+                if path.exists() {
+                    fs::remove_dir_all(path).map_err(Error::io(path))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads an installed mod, if it is present.
+    pub fn get_mod(&self, mod_id: &str) -> Result<Option<Mod>> {
         let mod_path = self.mods_path.join(mod_id);
         if mod_path.exists() {
-            Some(Mod::new(mod_path.as_path()))
+            Ok(Some(Mod::load(mod_path.as_path())?))
         } else {
-            None
+            Ok(None)
         }
     }
 
+    /// Loads every enabled mod, skipping those whose manifest is broken.
+    pub fn get_mods(&self) -> Result<Vec<Mod>> {
+        Ok(self.load_mods()?.into_iter().filter_map(|m| m.ok()).collect())
+    }
+
+    /// Loads every disabled mod, skipping those whose manifest is broken.
+    pub fn get_disabled_mods(&self) -> Result<Vec<Mod>> {
+        Ok(self
+            .load_disabled_mods()?
+            .into_iter()
+            .filter_map(|m| m.ok())
+            .collect())
+    }
+
+    /// Loads every installed mod, whether it is enabled or not.
+    pub fn get_all_mods(&self) -> Result<Vec<Mod>> {
+        let mut mods = self.get_mods()?;
+        mods.extend(self.get_disabled_mods()?);
+        Ok(mods)
+    }
+
+    /// Loads every enabled mod, keeping the errors for those whose manifest is broken.
+    pub fn load_mods(&self) -> Result<Vec<result::Result<Mod, ManifestError>>> {
+        load_mods_from(&self.mods_path)
+    }
+
+    /// Loads every disabled mod, keeping the errors for those whose manifest is broken.
+    pub fn load_disabled_mods(&self) -> Result<Vec<result::Result<Mod, ManifestError>>> {
+        load_mods_from(&self.disabled_mods_path)
+    }
+
+    /// Returns the directory in which mods are installed.
     pub fn get_mods_dir(&self) -> &Path {
         self.mods_path.as_path()
     }
 
-    pub fn make_mods_dir(&self) -> io::Result<()> {
-        fs::create_dir_all(&self.mods_path)
+    /// Returns the directory in which disabled mods are kept, out of the game's sight.
+    pub fn get_disabled_mods_dir(&self) -> &Path {
+        self.disabled_mods_path.as_path()
     }
 
-    pub fn remove_mod(&self, mod_id: &str) -> io::Result<()> {
-        let mod_path = self.mods_path.join(mod_id);
-        fs::remove_dir_all(mod_path)
+    /// Creates the mods directory if it doesn't already exist.
+    pub fn make_mods_dir(&self) -> Result<()> {
+        fs::create_dir_all(&self.mods_path).map_err(Error::io(&self.mods_path))
+    }
+
+    /// Deletes installed mods, refusing to if other installed mods depend on them, unless
+    /// `cascade` is set, in which case those are deleted as well. Returns the IDs of the deleted
+    /// mods.
+    ///
+    /// Mods whose manifest is broken can be deleted too, but aren't known to depend on any other.
+    pub fn remove_mods(&self, mod_ids: &[&str], cascade: bool) -> Result<Vec<String>> {
+        let installed_mods: BTreeMap<String, Mod> = self
+            .get_all_mods()?
+            .into_iter()
+            .map(|m| (m.id.clone(), m))
+            .collect();
+        for mod_id in mod_ids {
+            self.installed_mod_path(mod_id)?;
+        }
+        let removed_ids = with_dependents(&installed_mods, mod_ids, cascade)?;
+
+        // Remove dependents before the mods they depend on, even optionally.
+        let dependencies = removed_ids
+            .iter()
+            .map(|id| {
+                let dependency_ids = match installed_mods.get(id) {
+                    Some(m) => m
+                        .dependencies
+                        .iter()
+                        .chain(m.optional_dependencies.iter())
+                        .map(|d| d.id.clone())
+                        .collect(),
+                    None => Vec::new(),
+                };
+                (id.clone(), dependency_ids)
+            })
+            .collect();
+        let mut removed_ids = resolver::dependency_order(&dependencies);
+        removed_ids.reverse();
+        for mod_id in removed_ids.iter() {
+            self.remove_mod(mod_id)?;
+        }
+        Ok(removed_ids)
+    }
+
+    /// Deletes the mods that were only installed as dependencies, and that no mod requested by the
+    /// user depends on any more, directly or not. Returns the IDs of the deleted mods.
+    pub fn autoremove_mods(&self) -> Result<Vec<String>> {
+        let installed_mods = self.get_all_mods()?;
+        let lockfile = Lockfile::load(self)?;
+
+        // Keep every mod that was requested, and everything they depend on, even optionally.
+        let mut needed_ids: Vec<&str> = installed_mods
+            .iter()
+            .filter(|m| lockfile.reason(&m.id) == InstallReason::Explicit)
+            .map(|m| m.id.as_str())
+            .collect();
+        let mut i = 0;
+        while i < needed_ids.len() {
+            if let Some(needed_mod) = installed_mods.iter().find(|m| m.id == needed_ids[i]) {
+                let dependencies = needed_mod.dependencies.iter();
+                for dependency in dependencies.chain(needed_mod.optional_dependencies.iter()) {
+                    if !needed_ids.contains(&dependency.id.as_str()) {
+                        needed_ids.push(&dependency.id);
+                    }
+                }
+            }
+            i += 1;
+        }
+
+        let unneeded_ids: Vec<&str> = installed_mods
+            .iter()
+            .map(|m| m.id.as_str())
+            .filter(|id| !needed_ids.contains(id))
+            .collect();
+        self.remove_mods(&unneeded_ids, false)
+    }
+
+    /// Deletes an installed mod, whether it is enabled or not, and forgets it in the lockfile.
+    pub fn remove_mod(&self, mod_id: &str) -> Result<()> {
+        let mod_path = self.installed_mod_path(mod_id)?;
+        fs::remove_dir_all(&mod_path).map_err(Error::io(&mod_path))?;
+        let mut lockfile = Lockfile::load(self)?;
+        lockfile.sync(&self.get_all_mods()?);
+        lockfile.save(self)
+    }
+
+    /// Moves enabled mods to the disabled mods directory, where the game doesn't load them,
+    /// refusing to if other enabled mods depend on them, unless `cascade` is set, in which case
+    /// those are disabled as well. Returns the disabled mods.
+    pub fn disable_mods(&self, mod_ids: &[&str], cascade: bool) -> Result<Vec<Mod>> {
+        let mut enabled_mods: BTreeMap<String, Mod> = self
+            .get_mods()?
+            .into_iter()
+            .map(|m| (m.id.clone(), m))
+            .collect();
+        let disabled_mods = self.get_disabled_mods()?;
+        let mut requested_ids = Vec::new();
+        for mod_id in mod_ids {
+            self.installed_mod_path(mod_id)?;
+            if enabled_mods.contains_key(*mod_id) {
+                requested_ids.push(*mod_id);
+            } else if !disabled_mods.iter().any(|m| m.id == *mod_id) {
+                return Err(Error::NotInstalled {
+                    mod_id: mod_id.to_string(),
+                });
+            }
+        }
+
+        fs::create_dir_all(&self.disabled_mods_path)
+            .map_err(Error::io(&self.disabled_mods_path))?;
+        let mut moved_mods = Vec::new();
+        for mod_id in with_dependents(&enabled_mods, &requested_ids, cascade)? {
+            let destination = self.disabled_mods_path.join(&mod_id);
+            move_mod(&self.mods_path.join(&mod_id), &destination)?;
+            moved_mods.extend(enabled_mods.remove(&mod_id));
+        }
+        Ok(moved_mods)
+    }
+
+    /// Moves disabled mods back to the mods directory, along with the disabled mods they depend
+    /// on, checking first that they support the game, unless `force` is set, and that they are
+    /// compatible with the enabled mods. Returns the enabled mods.
+    pub fn enable_mods(&self, mod_ids: &[&str], force: bool) -> Result<Vec<Mod>> {
+        let enabled_mods = self.get_mods()?;
+        let mut disabled_mods: BTreeMap<String, Mod> = self
+            .get_disabled_mods()?
+            .into_iter()
+            .map(|m| (m.id.clone(), m))
+            .collect();
+        let mut enabled_ids = Vec::new();
+        for mod_id in mod_ids {
+            self.installed_mod_path(mod_id)?;
+            if disabled_mods.contains_key(*mod_id) {
+                enabled_ids.push(mod_id.to_string());
+            } else if !enabled_mods.iter().any(|m| m.id == *mod_id) {
+                return Err(Error::NotInstalled {
+                    mod_id: mod_id.to_string(),
+                });
+            }
+        }
+
+        // Enable the dependencies of the enabled mods too, unless they are already.
+        let mut i = 0;
+        while i < enabled_ids.len() {
+            let dependency_ids: Vec<String> = disabled_mods[&enabled_ids[i]]
+                .dependencies
+                .iter()
+                .map(|d| d.id.clone())
+                .filter(|id| disabled_mods.contains_key(id) && !enabled_ids.contains(id))
+                .filter(|id| !enabled_mods.iter().any(|m| &m.id == id))
+                .collect();
+            enabled_ids.extend(dependency_ids);
+            i += 1;
+        }
+        if !force {
+            for mod_id in enabled_ids.iter() {
+                self.check_game_version(&disabled_mods[mod_id])?;
+            }
+        }
+        let mods: Vec<&Mod> = enabled_mods
+            .iter()
+            .chain(enabled_ids.iter().map(|id| &disabled_mods[id]))
+            .collect();
+        check_compatibility(&mods)?;
+
+        self.make_mods_dir()?;
+        let mut moved_mods = Vec::new();
+        for mod_id in enabled_ids {
+            let source = self.disabled_mods_path.join(&mod_id);
+            move_mod(&source, &self.mods_path.join(&mod_id))?;
+            moved_mods.extend(disabled_mods.remove(&mod_id));
+        }
+        Ok(moved_mods)
+    }
+
+    /// Returns the directory of an installed mod, whether it is enabled or not. The ID must name
+    /// a directory directly within the mods directory or the disabled mods directory.
+    fn installed_mod_path(&self, mod_id: &str) -> Result<PathBuf> {
+        let mut components = Path::new(mod_id).components();
+        if let (Some(Component::Normal(_)), None) = (components.next(), components.next()) {
+            for mods_path in [&self.mods_path, &self.disabled_mods_path].iter() {
+                let mod_path = mods_path.join(mod_id);
+                if mod_path.is_dir() {
+                    return Ok(mod_path);
+                }
+            }
+        }
+        Err(Error::NotInstalled {
+            mod_id: mod_id.to_string(),
+        })
     }
 }
 
+/// Loads the mods in a directory, keeping the errors for those whose manifest is broken.
+fn load_mods_from(mods_path: &Path) -> Result<Vec<result::Result<Mod, ManifestError>>> {
+    let mut mods = Vec::new();
+    if !mods_path.is_dir() {
+        return Ok(mods);
+    }
+    let mut mod_paths = Vec::new();
+    for dir_entry in fs::read_dir(mods_path).map_err(Error::io(mods_path))? {
+        let path = dir_entry.map_err(Error::io(mods_path))?.path();
+        if path.is_dir() {
+            mod_paths.push(path);
+        }
+    }
+    mod_paths.sort();
+    for mod_path in mod_paths {
+        mods.push(Mod::load(mod_path.as_path()));
+    }
+    Ok(mods)
+}
+
+/// Moves the directory of a mod, replacing any other copy of the mod at the destination.
+fn move_mod(source: &Path, destination: &Path) -> Result<()> {
+    if destination.exists() {
+        fs::remove_dir_all(destination).map_err(Error::io(destination))?;
+    }
+    fs::rename(source, destination).map_err(Error::io(source))
+}
+
+/// Returns the IDs of some installed mods, followed by those of every installed mod that would be
+/// left with a missing dependency without them. Unless `cascade` is set, there mustn't be any.
+fn with_dependents(
+    installed_mods: &BTreeMap<String, Mod>,
+    mod_ids: &[&str],
+    cascade: bool,
+) -> Result<Vec<String>> {
+    let depends_on = |m: &Mod, mod_id: &str| m.dependencies.iter().any(|d| d.id == mod_id);
+    let mut ids: Vec<String> = mod_ids.iter().map(|id| id.to_string()).collect();
+    let mut i = 0;
+    while i < ids.len() {
+        let dependents: Vec<String> = installed_mods
+            .values()
+            .filter(|m| depends_on(m, &ids[i]) && !ids.contains(&m.id))
+            .map(|m| m.id.clone())
+            .collect();
+        if !dependents.is_empty() && !cascade {
+            return Err(Error::HasDependents {
+                mod_id: ids[i].clone(),
+                dependents,
+            });
+        }
+        ids.extend(dependents);
+        i += 1;
+    }
+    Ok(ids)
+}
+
+/// Returns whether `mod_id` is a valid mod ID, such as can be used in a dependency specifier.
+pub fn is_valid_mod_id(mod_id: &str) -> bool {
+    lazy_static! {
+        static ref MOD_ID_REGEX: Regex = Regex::new(MOD_ID).unwrap();
+    }
+    MOD_ID_REGEX.is_match(mod_id)
+}
+
+/// Checks that none of `mods` can't be installed along with another one of them.
+pub(crate) fn check_compatibility(mods: &[&Mod]) -> Result<()> {
+    for checked_mod in mods {
+        if let Some(other) = mods
+            .iter()
+            .find(|m| checked_mod.is_incompatible_with(&m.id, &m.version))
+        {
+            return Err(Error::IncompatibleMods {
+                mod_id: checked_mod.id.clone(),
+                version: checked_mod.version.to_string(),
+                other_id: other.id.clone(),
+                other_version: other.version.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn parse_game_version(version: &str) -> Option<Version> {
+    if let Ok(version) = Version::parse(version) {
+        return Some(version);
+    }
+    let parts: Vec<&str> = version.split('.').collect();
+    if parts.len() < 3 || parts.len() > 4 {
+        return None;
+    }
+    let year = parts[0].parse::<u64>().ok()?;
+    let month = parts[1].parse::<u64>().ok()?;
+    let mut letters = parts[2].chars();
+    let release = match (letters.next(), letters.next()) {
+        (Some(c), None) if c.is_ascii_alphabetic() => (c.to_ascii_lowercase() as u8 - b'a') as u64,
+        _ => return None,
+    };
+    let hotfix = match parts.get(3) {
+        Some(hotfix) => hotfix.parse::<u64>().ok()?,
+        None => 0,
+    };
+    if hotfix >= HOTFIXES_PER_RELEASE {
+        return None;
+    }
+    Some(Version::new(year, month, release * HOTFIXES_PER_RELEASE + hotfix))
+}
+
+/// Parses a `game_version` requirement, in which versions can be written the way the game
+/// numbers them (e.g. `>= 2019.1.b.2`).
+fn parse_game_version_requirement(requirement_string: &str) -> Result<VersionReq> {
+    lazy_static! {
+        static ref GAME_VERSION_REGEX: Regex = Regex::new(GAME_VERSION).unwrap();
+    }
+    let normalized = GAME_VERSION_REGEX.replace_all(requirement_string, |captures: &Captures| {
+        match parse_game_version(&captures[0]) {
+            Some(version) => version.to_string(),
+            None => captures[0].to_string(),
+        }
+    });
+    parse_requirement(&normalized).map_err(|reason| Error::InvalidVersionRequirement {
+        requirement: requirement_string.to_string(),
+        reason,
+    })
+}
+
+impl Platform {
+    pub const ALL: [Platform; 3] = [Platform::Windows, Platform::MacOs, Platform::Linux];
+
+    /// Determines which platform the game installed in `root` was built for, if any.
+    pub fn detect(root: &Path) -> Option<Platform> {
+        Platform::ALL
+            .iter()
+            .cloned()
+            .find(|platform| root.join(platform.exe_path()).is_file())
+    }
+
+    /// Returns the platform this program is running on, defaulting to Linux on other systems.
+    pub fn host() -> Platform {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else {
+            Platform::Linux
+        }
+    }
+
+    fn exe_path(&self) -> &'static str {
+        match self {
+            Platform::Windows => WINDOWS_EXE_PATH,
+            Platform::MacOs => MACOS_EXE_PATH,
+            Platform::Linux => LINUX_EXE_PATH,
+        }
+    }
+
+    fn data_path(&self) -> &'static str {
+        match self {
+            Platform::Windows => WINDOWS_DATA_PATH,
+            Platform::MacOs => MACOS_DATA_PATH,
+            Platform::Linux => LINUX_DATA_PATH,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Windows => "windows",
+            Platform::MacOs => "macos",
+            Platform::Linux => "linux",
+        })
+    }
+}
+
+impl str::FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Platform, String> {
+        match s {
+            "windows" => Ok(Platform::Windows),
+            "macos" => Ok(Platform::MacOs),
+            "linux" => Ok(Platform::Linux),
+            _ => Err(format!("Unknown platform '{}'", s)),
+        }
+    }
+}
+
+/// A mod, as described by its manifest.
 pub struct Mod {
     pub id: String,
     pub name: String,
@@ -169,82 +798,325 @@ pub struct Mod {
     pub description: String,
     pub description_long: String,
     pub dependencies: Vec<ModDependency>,
+    /// Mods that are only installed if requested, but are installed first when they are.
+    pub optional_dependencies: Vec<ModDependency>,
+    /// Mods that can't be installed along with this one, from the `incompatible` and `conflicts`
+    /// fields of the manifest.
+    pub incompatible: Vec<ModDependency>,
+    /// The versions of the game the mod works with, if it is restricted to some of them.
+    pub game_version: Option<VersionReq>,
 }
 
 impl Mod {
-    pub fn new(mod_dir: &Path) -> Mod {
+    /// Loads a mod from its directory, whose name is the mod's ID.
+    pub fn load(mod_dir: &Path) -> result::Result<Mod, ManifestError> {
+        let id = match mod_dir.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => String::new(),
+        };
+        let manifest_path = mod_dir.join(MANIFEST_PATH);
+        let error = |kind| ManifestError {
+            mod_id: id.clone(),
+            path: manifest_path.clone(),
+            kind,
+        };
+
         // Load and validate the mod's manifest
-        let manifest: serde_json::Value =
-            serde_json::from_reader(File::open(mod_dir.join("manifest.json")).unwrap()).unwrap();
-        Mod {
-            id: mod_dir.file_name().unwrap().to_str().unwrap().to_string(),
-            name: manifest["name"].to_string(),
-            author: manifest["author"].to_string(),
-            version: Version::parse(manifest["version"].as_str().unwrap()).unwrap(),
-            description: manifest["description"].to_string(),
-            description_long: manifest["description_long"].to_string(),
-            dependencies: manifest["dependencies"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .map(|dependency| ModDependency::parse(dependency.as_str().unwrap()).unwrap())
-                .collect(),
+        let manifest_file =
+            File::open(&manifest_path).map_err(|e| error(ManifestErrorKind::Io(e)))?;
+        let manifest: serde_json::Value = serde_json::from_reader(manifest_file)
+            .map_err(|e| error(ManifestErrorKind::Json(e)))?;
+        let version = match manifest["version"].as_str() {
+            Some(v) => Version::parse(v).map_err(|e| {
+                error(ManifestErrorKind::InvalidField {
+                    field: "version".to_string(),
+                    reason: e.to_string(),
+                })
+            })?,
+            None => return Err(error(ManifestErrorKind::MissingField("version"))),
+        };
+        let dependencies = parse_dependencies(&manifest, "dependencies").map_err(&error)?;
+        let optional_dependencies =
+            parse_dependencies(&manifest, "optional_dependencies").map_err(&error)?;
+        let mut incompatible = parse_dependencies(&manifest, "incompatible").map_err(&error)?;
+        incompatible.extend(parse_dependencies(&manifest, "conflicts").map_err(&error)?);
+
+        let game_version = match &manifest["game_version"] {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(requirement) => {
+                Some(parse_game_version_requirement(requirement).map_err(|e| {
+                    error(ManifestErrorKind::InvalidField {
+                        field: "game_version".to_string(),
+                        reason: e.to_string(),
+                    })
+                })?)
+            }
+            _ => {
+                return Err(error(ManifestErrorKind::InvalidField {
+                    field: "game_version".to_string(),
+                    reason: "expected a string".to_string(),
+                }))
+            }
+        };
+
+        Ok(Mod {
+            name: manifest["name"].as_str().unwrap_or("").to_string(),
+            author: manifest["author"].as_str().unwrap_or("").to_string(),
+            version,
+            description: manifest["description"].as_str().unwrap_or("").to_string(),
+            description_long: manifest["description_long"].as_str().unwrap_or("").to_string(),
+            dependencies,
+            optional_dependencies,
+            incompatible,
+            game_version,
+            id,
+        })
+    }
+
+    /// Returns whether this mod can't be installed along with a version of another mod.
+    pub fn is_incompatible_with(&self, mod_id: &str, version: &Version) -> bool {
+        self.incompatible
+            .iter()
+            .any(|d| d.id == mod_id && d.matches(version))
+    }
+}
+
+/// Parses a list of dependency specifiers from a field of a manifest, which may be absent.
+fn parse_dependencies(
+    manifest: &serde_json::Value,
+    field: &str,
+) -> result::Result<Vec<ModDependency>, ManifestErrorKind> {
+    let mut dependencies = Vec::new();
+    match &manifest[field] {
+        serde_json::Value::Null => (), // No dependencies
+        serde_json::Value::Array(values) => for (i, value) in values.iter().enumerate() {
+            let dependency = match value.as_str() {
+                Some(d) => ModDependency::parse(d).map_err(|e| e.to_string()),
+                None => Err("expected a string".to_string()),
+            };
+            match dependency {
+                Ok(d) => dependencies.push(d),
+                Err(reason) => {
+                    return Err(ManifestErrorKind::InvalidField {
+                        field: format!("{}[{}]", field, i),
+                        reason,
+                    })
+                }
+            }
+        },
+        _ => {
+            return Err(ManifestErrorKind::InvalidField {
+                field: field.to_string(),
+                reason: "expected an array".to_string(),
+            })
         }
     }
+    Ok(dependencies)
+}
+
+/// An error encountered while loading a mod's manifest.
+#[derive(Debug)]
+pub struct ManifestError {
+    pub mod_id: String,
+    pub path: PathBuf,
+    pub kind: ManifestErrorKind,
 }
 
+/// The reason a mod's manifest could not be loaded.
+#[derive(Debug)]
+pub enum ManifestErrorKind {
+    Io(io::Error),
+    Json(serde_json::Error),
+    MissingField(&'static str),
+    InvalidField { field: String, reason: String },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid manifest for mod '{}' ({}): ",
+            self.mod_id,
+            self.path.display()
+        )?;
+        match &self.kind {
+            ManifestErrorKind::Io(e) => write!(f, "{}", e),
+            ManifestErrorKind::Json(e) => write!(f, "{}", e),
+            ManifestErrorKind::MissingField(field) => write!(f, "missing field '{}'", field),
+            ManifestErrorKind::InvalidField { field, reason } => {
+                write!(f, "invalid field '{}': {}", field, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for ManifestError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match &self.kind {
+            ManifestErrorKind::Io(e) => Some(e),
+            ManifestErrorKind::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A requirement on a mod, optionally restricted to some of its versions.
+#[derive(Clone)]
 pub struct ModDependency {
     pub id: String,
-    pub operator: Option<ModDependencyOperator>,
-    pub version: Option<Version>,
+    pub requirement: VersionReq,
 }
 
 impl ModDependency {
-    pub fn new(
-        id: String,
-        operator: Option<ModDependencyOperator>,
-        version: Option<Version>,
-    ) -> ModDependency {
-        ModDependency {
-            id,
-            operator,
-            version,
-        }
+    pub fn new(id: String, requirement: VersionReq) -> ModDependency {
+        ModDependency { id, requirement }
     }
 
-    pub fn parse(dependency_string: &str) -> Result<ModDependency, String> {
+    /// Returns whether `version` of the mod satisfies this dependency.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.requirement.matches(version)
+    }
+
+    /// Parses a dependency specifier such as `mod_id`, `mod_id >= 1.0.0`, `mod_id ^1.2` or
+    /// `mod_id >= 1.0, < 2.0`.
+    pub fn parse(dependency_string: &str) -> Result<ModDependency> {
         lazy_static! {
-            static ref VERSION_REGEX: Regex = Regex::new(MOD_DEPENDENCY_VERSION).unwrap();
+            static ref DEPENDENCY_REGEX: Regex = Regex::new(MOD_DEPENDENCY).unwrap();
         }
-        let captures = VERSION_REGEX.captures(dependency_string).unwrap();
+        let captures = match DEPENDENCY_REGEX.captures(dependency_string) {
+            Some(c) => c,
+            None => {
+                return Err(Error::InvalidDependency {
+                    specifier: dependency_string.to_string(),
+                    reason: "expected a mod ID, optionally followed by a version".to_string(),
+                })
+            }
+        };
+        let requirement = match captures.get(2) {
+            Some(requirement) => parse_requirement(requirement.as_str()).map_err(|reason| {
+                Error::InvalidDependency {
+                    specifier: dependency_string.to_string(),
+                    reason,
+                }
+            })?,
+            None => VersionReq::any(),
+        };
         Ok(ModDependency::new(
             captures.get(1).unwrap().as_str().to_string(),
-            match captures.get(2) {
-                Some(op) => Some(match op.as_str() {
-                    "<" => ModDependencyOperator::LessThan,
-                    "<=" => ModDependencyOperator::LessThanOrEqual,
-                    ">" => ModDependencyOperator::GreaterThan,
-                    ">=" => ModDependencyOperator::GreaterThanOrEqual,
-                    "==" => ModDependencyOperator::Equal,
-                    _ => panic!("Unexpected dependency operator"),
-                }),
-                None => None,
-            },
-            match captures.get(3) {
-                Some(version) => Some(match Version::parse(version.as_str()) {
-                    Ok(v) => v,
-                    Err(e) => return Err(format!("Invalid version specifier: {}", e)),
-                }),
-                None => None,
-            },
+            requirement,
         ))
     }
 }
 
-pub enum ModDependencyOperator {
-    LessThan,
-    LessThanOrEqual,
-    GreaterThan,
-    GreaterThanOrEqual,
-    Equal,
+impl fmt::Display for ModDependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.requirement == VersionReq::any() {
+            write!(f, "{}", self.id)
+        } else {
+            write!(f, "{} {}", self.id, self.requirement)
+        }
+    }
+}
+
+/// Parses a version requirement such as `>= 1.0.0`, `^1.2`, `~1.2`, `1.*` or `>= 1.0, < 2.0`.
+///
+/// A bare version such as `1.2.0` is read as `^1.2.0`, and `==` is accepted as a synonym of `=`.
+fn parse_requirement(requirement_string: &str) -> result::Result<VersionReq, String> {
+    VersionReq::parse(&requirement_string.replace("==", "=")).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    /// Creates an empty Linux installation of the game in a temporary directory.
+    fn temp_game() -> (TempDir, Game) {
+        let dir = TempDir::new("frangiclave-game").unwrap();
+        let root = dir.path().to_path_buf();
+        let game = Game::with_platform(&root, Platform::Linux);
+        fs::create_dir_all(&game.managed_path).unwrap();
+        fs::create_dir_all(&game.mods_path).unwrap();
+        fs::write(&game.exe_path, b"").unwrap();
+        fs::write(&game.assembly_path, b"vanilla").unwrap();
+        (dir, game)
+    }
+
+    #[test]
+    fn parse_game_versions() {
+        assert_eq!(parse_game_version("2019.1.b.2"), Some(Version::new(2019, 1, 102)));
+        assert_eq!(parse_game_version("2019.01.b"), Some(Version::new(2019, 1, 100)));
+        assert_eq!(parse_game_version("2018.12.A.13"), Some(Version::new(2018, 12, 13)));
+        assert_eq!(parse_game_version("2019.1.0"), Some(Version::new(2019, 1, 0)));
+        assert_eq!(parse_game_version("2019.1.b.100"), None);
+        assert_eq!(parse_game_version("2019.1.bb"), None);
+        assert_eq!(parse_game_version("2019.x.b"), None);
+    }
+
+    #[test]
+    fn game_versions_compare_hotfixes() {
+        let requirement = parse_game_version_requirement(">= 2019.1.b.2").unwrap();
+        assert!(requirement.matches(&parse_game_version("2019.1.b.2").unwrap()));
+        assert!(requirement.matches(&parse_game_version("2019.1.c").unwrap()));
+        assert!(!requirement.matches(&parse_game_version("2019.1.b.1").unwrap()));
+        assert!(!requirement.matches(&parse_game_version("2019.01.a.5").unwrap()));
+    }
+
+    #[test]
+    fn restore_removes_old_backups() {
+        let (_dir, game) = temp_game();
+        fs::write(&game.assembly_backup_path, b"vanilla").unwrap();
+        fs::write(&game.assembly_path, b"patched WasHere\0").unwrap();
+        let old_backup_path = game.managed_path.join("Assembly-CSharp-backup-0123456789ab.dll");
+        fs::write(&old_backup_path, b"older").unwrap();
+
+        game.restore_assembly(false).unwrap();
+        assert_eq!(fs::read(&game.assembly_path).unwrap(), b"vanilla");
+        assert!(!game.assembly_backup_path.exists());
+        assert!(!old_backup_path.exists());
+    }
+
+    #[test]
+    fn remove_rejects_paths_outside_the_mods_directory() {
+        let (_dir, game) = temp_game();
+        let outside_path = game.mods_path.parent().unwrap().join("outside");
+        fs::create_dir_all(&outside_path).unwrap();
+        let absolute_path = outside_path.to_string_lossy().into_owned();
+
+        for mod_id in ["..", "../outside", "../../Managed", &absolute_path].iter() {
+            match game.remove_mods(&[mod_id], true) {
+                Err(Error::NotInstalled { .. }) => (),
+                _ => panic!("'{}' shouldn't be removed", mod_id),
+            }
+            assert!(game.remove_mod(mod_id).is_err());
+            assert!(game.disable_mods(&[mod_id], true).is_err());
+            assert!(game.enable_mods(&[mod_id], true).is_err());
+        }
+        assert!(game.mods_path.is_dir());
+        assert!(outside_path.is_dir());
+        assert!(game.managed_path.is_dir());
+    }
+
+    #[test]
+    fn enable_checks_the_game_version_unless_forced() {
+        let (_dir, game) = temp_game();
+        fs::create_dir_all(game.version_path.parent().unwrap()).unwrap();
+        fs::write(&game.version_path, "2019.1.b.1").unwrap();
+        let mod_path = game.disabled_mods_path.join("new");
+        fs::create_dir_all(&mod_path).unwrap();
+        fs::write(
+            mod_path.join(MANIFEST_PATH),
+            r#"{"version": "1.0.0", "game_version": ">= 2020.1.a"}"#,
+        )
+        .unwrap();
+
+        match game.enable_mods(&["new"], false) {
+            Err(Error::IncompatibleGameVersion { .. }) => (),
+            _ => panic!("the mod doesn't support the game"),
+        }
+        assert!(mod_path.is_dir());
+        let enabled_mods = game.enable_mods(&["new"], true).unwrap();
+        assert_eq!(enabled_mods.len(), 1);
+        assert!(game.mods_path.join("new").is_dir());
+    }
 }
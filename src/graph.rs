@@ -0,0 +1,315 @@
+use semver::VersionReq;
+use std::collections::BTreeMap;
+
+use error::Result;
+use game::{Game, Mod, ModDependency};
+
+/// The dependencies between the mods installed in a game, whether they are enabled or not.
+pub struct ModGraph {
+    mods: BTreeMap<String, Mod>,
+    disabled_ids: Vec<String>,
+}
+
+/// A dependency of an installed mod.
+pub struct Edge<'a> {
+    pub dependent: &'a Mod,
+    pub dependency: &'a ModDependency,
+    pub status: EdgeStatus<'a>,
+}
+
+/// Whether the dependency of an installed mod is satisfied.
+pub enum EdgeStatus<'a> {
+    /// A matching version of the dependency is installed.
+    Satisfied(&'a Mod),
+    /// The dependency isn't installed.
+    Missing,
+    /// The dependency is installed, but in a version that doesn't match.
+    Mismatched(&'a Mod),
+}
+
+impl ModGraph {
+    /// Builds the graph of the mods installed in a game.
+    pub fn load(game: &Game) -> Result<ModGraph> {
+        Ok(ModGraph::new(game.get_mods()?, game.get_disabled_mods()?))
+    }
+
+    /// Builds the graph of a set of enabled and disabled mods.
+    pub fn new(mods: Vec<Mod>, disabled_mods: Vec<Mod>) -> ModGraph {
+        let disabled_ids = disabled_mods.iter().map(|m| m.id.clone()).collect();
+        ModGraph {
+            mods: mods
+                .into_iter()
+                .chain(disabled_mods)
+                .map(|m| (m.id.clone(), m))
+                .collect(),
+            disabled_ids,
+        }
+    }
+
+    /// Returns whether an installed mod is disabled.
+    pub fn is_disabled(&self, mod_id: &str) -> bool {
+        self.disabled_ids.iter().any(|id| id == mod_id)
+    }
+
+    /// Returns an installed mod.
+    pub fn get(&self, mod_id: &str) -> Option<&Mod> {
+        self.mods.get(mod_id)
+    }
+
+    /// Returns every installed mod, ordered by ID.
+    pub fn mods(&self) -> Vec<&Mod> {
+        self.mods.values().collect()
+    }
+
+    /// Returns the dependencies of an installed mod.
+    pub fn dependencies<'a>(&'a self, mod_id: &str) -> Vec<Edge<'a>> {
+        match self.mods.get(mod_id) {
+            Some(m) => m.dependencies.iter().map(|d| self.edge(m, d)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the dependencies of other installed mods on a mod, whether it is installed or not.
+    pub fn dependents<'a>(&'a self, mod_id: &str) -> Vec<Edge<'a>> {
+        self.mods
+            .values()
+            .flat_map(|m| {
+                m.dependencies
+                    .iter()
+                    .filter(|d| d.id == mod_id)
+                    .map(move |d| self.edge(m, d))
+            })
+            .collect()
+    }
+
+    /// Returns the mods that no other mod depends on, followed by one mod of each group of mods
+    /// that only depend on each other in a cycle, so that every mod can be reached from them.
+    pub fn roots(&self) -> Vec<&Mod> {
+        let mut roots: Vec<&Mod> = self
+            .mods
+            .values()
+            .filter(|m| self.dependents(&m.id).is_empty())
+            .collect();
+        let mut reachable = Vec::new();
+        for root in roots.iter() {
+            reachable.extend(self.reachable(&root.id));
+        }
+        for installed_mod in self.mods.values() {
+            if !reachable.contains(&installed_mod.id.as_str()) {
+                roots.push(installed_mod);
+                reachable.extend(self.reachable(&installed_mod.id));
+            }
+        }
+        roots
+    }
+
+    /// Returns the IDs of an installed mod and of every installed mod it depends on, directly or
+    /// not.
+    pub fn reachable(&self, mod_id: &str) -> Vec<&str> {
+        let mut reachable: Vec<&str> = match self.mods.get(mod_id) {
+            Some(m) => vec![&m.id],
+            None => return Vec::new(),
+        };
+        let mut i = 0;
+        while i < reachable.len() {
+            for edge in self.dependencies(reachable[i]) {
+                match edge.status {
+                    EdgeStatus::Satisfied(m) | EdgeStatus::Mismatched(m) => {
+                        if !reachable.contains(&m.id.as_str()) {
+                            reachable.push(&m.id);
+                        }
+                    }
+                    EdgeStatus::Missing => (),
+                }
+            }
+            i += 1;
+        }
+        reachable
+    }
+
+    /// Describes the graph in the DOT language, for rendering with Graphviz. If `mod_id` is given,
+    /// only that mod and its dependencies are included.
+    ///
+    /// Missing dependencies are drawn with dashed lines, version mismatches in red, and disabled
+    /// mods in gray.
+    pub fn to_dot(&self, mod_id: Option<&str>) -> String {
+        let mod_ids: Vec<&str> = match mod_id {
+            Some(id) => self.reachable(id),
+            None => self.mods.keys().map(|id| id.as_str()).collect(),
+        };
+        let mut nodes = Vec::new();
+        let mut edges = Vec::new();
+        for id in mod_ids.iter() {
+            let installed_mod = &self.mods[*id];
+            let label = escape(&format!("{} {}", id, installed_mod.version));
+            nodes.push(if self.is_disabled(id) {
+                format!(
+                    "    \"{}\" [label=\"{} (disabled)\", color=gray, fontcolor=gray];",
+                    escape(id),
+                    label
+                )
+            } else {
+                format!("    \"{}\" [label=\"{}\"];", escape(id), label)
+            });
+            for edge in self.dependencies(id) {
+                let mut attributes = Vec::new();
+                if edge.dependency.requirement != VersionReq::any() {
+                    let requirement = edge.dependency.requirement.to_string();
+                    attributes.push(format!("label=\"{}\"", escape(&requirement)));
+                }
+                match edge.status {
+                    EdgeStatus::Satisfied(_) => (),
+                    EdgeStatus::Missing => {
+                        let node = format!(
+                            "    \"{0}\" [label=\"{0}\", style=dashed];",
+                            escape(&edge.dependency.id)
+                        );
+                        if !nodes.contains(&node) {
+                            nodes.push(node);
+                        }
+                        attributes.push("style=dashed".to_string());
+                    }
+                    EdgeStatus::Mismatched(_) => attributes.push("color=red".to_string()),
+                }
+                let mut line = format!(
+                    "    \"{}\" -> \"{}\"",
+                    escape(id),
+                    escape(&edge.dependency.id)
+                );
+                if !attributes.is_empty() {
+                    line = format!("{} [{}]", line, attributes.join(", "));
+                }
+                edges.push(format!("{};", line));
+            }
+        }
+
+        let mut dot = String::from("digraph mods {\n");
+        for line in nodes.iter().chain(edges.iter()) {
+            dot.push_str(line);
+            dot.push('\n');
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn edge<'a>(&'a self, dependent: &'a Mod, dependency: &'a ModDependency) -> Edge<'a> {
+        let status = match self.mods.get(&dependency.id) {
+            Some(m) if dependency.matches(&m.version) => EdgeStatus::Satisfied(m),
+            Some(m) => EdgeStatus::Mismatched(m),
+            None => EdgeStatus::Missing,
+        };
+        Edge {
+            dependent,
+            dependency,
+            status,
+        }
+    }
+}
+
+/// Escapes a string to be quoted in the DOT language.
+fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::Version;
+
+    fn installed_mod(id: &str, version: &str, dependencies: &[&str]) -> Mod {
+        Mod {
+            id: id.to_string(),
+            name: id.to_string(),
+            author: String::new(),
+            version: Version::parse(version).unwrap(),
+            description: String::new(),
+            description_long: String::new(),
+            dependencies: dependencies
+                .iter()
+                .map(|d| ModDependency::parse(d).unwrap())
+                .collect(),
+            optional_dependencies: Vec::new(),
+            incompatible: Vec::new(),
+            game_version: None,
+        }
+    }
+
+    fn ids<'a>(mods: Vec<&'a Mod>) -> Vec<&'a str> {
+        mods.into_iter().map(|m| m.id.as_str()).collect()
+    }
+
+    #[test]
+    fn roots_include_one_mod_of_each_cycle() {
+        let graph = ModGraph::new(
+            vec![
+                installed_mod("a", "1.0.0", &["b"]),
+                installed_mod("b", "1.0.0", &[]),
+                installed_mod("c", "1.0.0", &["d"]),
+                installed_mod("d", "1.0.0", &["c"]),
+            ],
+            Vec::new(),
+        );
+        assert_eq!(ids(graph.roots()), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn reachable_follows_installed_dependencies() {
+        let graph = ModGraph::new(
+            vec![
+                installed_mod("a", "1.0.0", &["b", "missing"]),
+                installed_mod("b", "1.0.0", &["c >= 2.0.0"]),
+                installed_mod("c", "1.0.0", &["a"]),
+                installed_mod("d", "1.0.0", &[]),
+            ],
+            Vec::new(),
+        );
+        assert_eq!(graph.reachable("a"), vec!["a", "b", "c"]);
+        assert_eq!(graph.reachable("d"), vec!["d"]);
+        assert!(graph.reachable("missing").is_empty());
+    }
+
+    #[test]
+    fn disabled_mods_are_part_of_the_graph() {
+        let graph = ModGraph::new(
+            vec![installed_mod("a", "1.0.0", &["b"])],
+            vec![installed_mod("b", "1.0.0", &[])],
+        );
+        assert!(graph.is_disabled("b"));
+        assert!(!graph.is_disabled("a"));
+        assert_eq!(ids(graph.roots()), vec!["a"]);
+        let dependents = graph.dependents("b");
+        assert_eq!(dependents.len(), 1);
+        assert_eq!(dependents[0].dependent.id, "a");
+        match dependents[0].status {
+            EdgeStatus::Satisfied(m) => assert_eq!(m.id, "b"),
+            _ => panic!("the dependency should be satisfied"),
+        }
+    }
+
+    #[test]
+    fn to_dot_marks_missing_mismatched_and_disabled_mods() {
+        let graph = ModGraph::new(
+            vec![installed_mod("a", "1.0.0", &["b >= 2.0.0", "c"])],
+            vec![installed_mod("b", "1.0.0", &[])],
+        );
+        assert_eq!(
+            graph.to_dot(None),
+            "digraph mods {\n\
+             \x20   \"a\" [label=\"a 1.0.0\"];\n\
+             \x20   \"c\" [label=\"c\", style=dashed];\n\
+             \x20   \"b\" [label=\"b 1.0.0 (disabled)\", color=gray, fontcolor=gray];\n\
+             \x20   \"a\" -> \"b\" [label=\">= 2.0.0\", color=red];\n\
+             \x20   \"a\" -> \"c\" [style=dashed];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn to_dot_escapes_quotes_and_backslashes() {
+        let graph = ModGraph::new(vec![installed_mod("a\"b\\", "1.0.0", &[])], Vec::new());
+        assert_eq!(
+            graph.to_dot(Some("a\"b\\")),
+            "digraph mods {\n    \"a\\\"b\\\\\" [label=\"a\\\"b\\\\ 1.0.0\"];\n}\n"
+        );
+    }
+}
@@ -0,0 +1,36 @@
+//! Patching and mod management for Cultist Simulator.
+//!
+//! A `Game` points to an installation of Cultist Simulator, which can be patched to load mods and
+//! from which installed mods (`Mod`) can be listed and removed. A `Repo` downloads mods from a
+//! remote repository and installs them, along with their dependencies (`ModDependency`).
+
+#[macro_use]
+extern crate lazy_static;
+
+#[macro_use]
+extern crate serde_derive;
+
+extern crate fs_extra;
+extern crate regex;
+extern crate reqwest;
+extern crate semver;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate tempdir;
+extern crate zip;
+
+pub mod config;
+pub mod discovery;
+pub mod error;
+pub mod game;
+pub mod graph;
+pub mod lockfile;
+pub mod patch;
+pub mod repo;
+pub mod resolver;
+mod transaction;
+
+pub use error::{Error, Result};
+pub use game::{Game, ManifestError, Mod, ModDependency, PatchStatus, Platform};
+pub use repo::{ModUpdate, Repo};
@@ -0,0 +1,259 @@
+use semver::Version;
+use serde::de;
+use serde::{Deserialize, Deserializer};
+use serde_json;
+use std::fs::File;
+use std::path::PathBuf;
+use std::result;
+
+use error::{Error, Result};
+use game;
+use game::{Game, Mod};
+
+const LOCKFILE_PATH: &'static str = "frangiclave.lock";
+
+/// The exact set of mods installed in a game, kept in the mods directory so that it can be
+/// reproduced elsewhere.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub mods: Vec<LockedMod>,
+}
+
+/// An installed mod, as recorded in the lockfile.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LockedMod {
+    /// Lockfiles are meant to be shared, so IDs that could point outside the mods directory are
+    /// rejected.
+    #[serde(deserialize_with = "deserialize_mod_id")]
+    pub id: String,
+    pub version: Version,
+    /// The URL of the repository the mod was downloaded from, unless it was installed by hand.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// The SHA-256 checksum of the archive the mod was installed from.
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Mods locked before reasons were recorded are assumed to have been requested.
+    #[serde(default = "InstallReason::explicit")]
+    pub reason: InstallReason,
+}
+
+/// Why a mod was installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallReason {
+    /// The mod was requested by the user.
+    Explicit,
+    /// The mod was installed because another mod depends on it.
+    Dependency,
+}
+
+impl InstallReason {
+    fn explicit() -> InstallReason {
+        InstallReason::Explicit
+    }
+}
+
+fn deserialize_mod_id<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> result::Result<String, D::Error> {
+    let mod_id = String::deserialize(deserializer)?;
+    if game::is_valid_mod_id(&mod_id) {
+        Ok(mod_id)
+    } else {
+        Err(de::Error::custom(format!("invalid mod ID '{}'", mod_id)))
+    }
+}
+
+impl Lockfile {
+    /// Loads the lockfile of a game, or returns an empty one if there is none.
+    pub fn load(game: &Game) -> Result<Lockfile> {
+        let path = Lockfile::path(game);
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let file = File::open(&path).map_err(Error::io(&path))?;
+        serde_json::from_reader(file).map_err(Error::json(&path))
+    }
+
+    /// Writes the lockfile of a game.
+    pub fn save(&self, game: &Game) -> Result<()> {
+        game.make_mods_dir()?;
+        let path = Lockfile::path(game);
+        let file = File::create(&path).map_err(Error::io(&path))?;
+        serde_json::to_writer_pretty(file, self).map_err(Error::json(&path))
+    }
+
+    /// Returns the location of the lockfile of a game.
+    pub fn path(game: &Game) -> PathBuf {
+        game.get_mods_dir().join(LOCKFILE_PATH)
+    }
+
+    /// Returns the entry for a mod, if it is locked.
+    pub fn get(&self, mod_id: &str) -> Option<&LockedMod> {
+        self.mods.iter().find(|m| m.id == mod_id)
+    }
+
+    /// Records a mod, replacing any previous entry for it.
+    pub fn lock(&mut self, locked_mod: LockedMod) {
+        self.mods.retain(|m| m.id != locked_mod.id);
+        self.mods.push(locked_mod);
+        self.mods.sort_by(|a, b| a.id.cmp(&b.id));
+    }
+
+    /// Returns why a mod was installed, assuming it was requested if it isn't locked.
+    pub fn reason(&self, mod_id: &str) -> InstallReason {
+        self.get(mod_id)
+            .map(|m| m.reason)
+            .unwrap_or(InstallReason::Explicit)
+    }
+
+    /// Records that a mod was requested by the user, if it is locked.
+    pub fn mark_explicit(&mut self, mod_id: &str) {
+        if let Some(locked_mod) = self.mods.iter_mut().find(|m| m.id == mod_id) {
+            locked_mod.reason = InstallReason::Explicit;
+        }
+    }
+
+    /// Brings the lockfile in line with the mods that are actually installed, forgetting those
+    /// that were removed, and recording those that were installed or changed by hand.
+    pub fn sync(&mut self, installed_mods: &[Mod]) {
+        self.mods
+            .retain(|m| installed_mods.iter().any(|i| i.id == m.id));
+        for installed_mod in installed_mods {
+            if self.get(&installed_mod.id).map(|m| &m.version) == Some(&installed_mod.version) {
+                continue;
+            }
+            let reason = self.reason(&installed_mod.id);
+            self.lock(LockedMod {
+                id: installed_mod.id.clone(),
+                version: installed_mod.version.clone(),
+                source: None,
+                checksum: None,
+                reason,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use game::Platform;
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn temp_game() -> (TempDir, Game) {
+        let dir = TempDir::new("frangiclave-lockfile").unwrap();
+        let game = Game::with_platform(&dir.path().to_path_buf(), Platform::Linux);
+        (dir, game)
+    }
+
+    fn installed_mod(id: &str, version: &str) -> Mod {
+        Mod {
+            id: id.to_string(),
+            name: id.to_string(),
+            author: String::new(),
+            version: Version::parse(version).unwrap(),
+            description: String::new(),
+            description_long: String::new(),
+            dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
+            incompatible: Vec::new(),
+            game_version: None,
+        }
+    }
+
+    fn locked_mod(id: &str, version: &str, reason: InstallReason) -> LockedMod {
+        LockedMod {
+            id: id.to_string(),
+            version: Version::parse(version).unwrap(),
+            source: Some("http://example.com".to_string()),
+            checksum: Some("abc".to_string()),
+            reason,
+        }
+    }
+
+    #[test]
+    fn load_missing_lockfile() {
+        let (_dir, game) = temp_game();
+        assert!(Lockfile::load(&game).unwrap().mods.is_empty());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (_dir, game) = temp_game();
+        let mut lockfile = Lockfile::default();
+        lockfile.lock(locked_mod("lib", "1.0.0", InstallReason::Dependency));
+        lockfile.lock(locked_mod("foo", "2.0.0", InstallReason::Explicit));
+        lockfile.save(&game).unwrap();
+
+        let loaded = Lockfile::load(&game).unwrap();
+        let ids: Vec<&str> = loaded.mods.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["foo", "lib"]);
+        let lib = loaded.get("lib").unwrap();
+        assert_eq!(lib.version, Version::new(1, 0, 0));
+        assert_eq!(lib.source.as_ref().unwrap(), "http://example.com");
+        assert_eq!(lib.checksum.as_ref().unwrap(), "abc");
+        assert_eq!(lib.reason, InstallReason::Dependency);
+    }
+
+    #[test]
+    fn load_defaults_to_explicit() {
+        let (_dir, game) = temp_game();
+        fs::create_dir_all(game.get_mods_dir()).unwrap();
+        let contents = r#"{"mods": [{"id": "foo", "version": "1.0.0"}]}"#;
+        fs::write(Lockfile::path(&game), contents).unwrap();
+
+        let lockfile = Lockfile::load(&game).unwrap();
+        assert_eq!(lockfile.reason("foo"), InstallReason::Explicit);
+        assert_eq!(lockfile.get("foo").unwrap().source, None);
+    }
+
+    #[test]
+    fn load_rejects_invalid_ids() {
+        let (_dir, game) = temp_game();
+        fs::create_dir_all(game.get_mods_dir()).unwrap();
+        for id in ["../..", "..", "/etc", "a/b", ""].iter() {
+            let contents = format!(r#"{{"mods": [{{"id": "{}", "version": "1.0.0"}}]}}"#, id);
+            fs::write(Lockfile::path(&game), contents).unwrap();
+            match Lockfile::load(&game) {
+                Err(Error::Json { .. }) => (),
+                Err(e) => panic!("unexpected error for '{}': {}", id, e),
+                Ok(_) => panic!("'{}' should have been rejected", id),
+            }
+        }
+    }
+
+    #[test]
+    fn sync_follows_installed_mods() {
+        let mut lockfile = Lockfile::default();
+        lockfile.lock(locked_mod("kept", "1.0.0", InstallReason::Explicit));
+        lockfile.lock(locked_mod("changed", "1.0.0", InstallReason::Dependency));
+        lockfile.lock(locked_mod("removed", "1.0.0", InstallReason::Explicit));
+        lockfile.sync(&[
+            installed_mod("kept", "1.0.0"),
+            installed_mod("changed", "1.1.0"),
+            installed_mod("added", "0.1.0"),
+        ]);
+
+        let ids: Vec<&str> = lockfile.mods.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["added", "changed", "kept"]);
+        assert_eq!(lockfile.get("kept").unwrap().checksum.as_ref().unwrap(), "abc");
+        let changed = lockfile.get("changed").unwrap();
+        assert_eq!(changed.version, Version::new(1, 1, 0));
+        assert_eq!(changed.source, None);
+        assert_eq!(changed.reason, InstallReason::Dependency);
+        assert_eq!(lockfile.reason("added"), InstallReason::Explicit);
+    }
+
+    #[test]
+    fn mark_explicit() {
+        let mut lockfile = Lockfile::default();
+        lockfile.lock(locked_mod("lib", "1.0.0", InstallReason::Dependency));
+        lockfile.mark_explicit("lib");
+        lockfile.mark_explicit("missing");
+        assert_eq!(lockfile.reason("lib"), InstallReason::Explicit);
+        assert!(lockfile.get("missing").is_none());
+    }
+}
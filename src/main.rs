@@ -1,27 +1,22 @@
-#[macro_use]
-extern crate lazy_static;
-
 #[macro_use]
 extern crate serde_derive;
 
 extern crate clap;
-extern crate fs_extra;
-extern crate regex;
-extern crate reqwest;
-extern crate semver;
+extern crate frangiclave_mod_manager;
 extern crate serde;
 extern crate serde_json;
-extern crate tempdir;
-extern crate zip;
 
-mod game;
-mod patch;
-mod repo;
-
-use game::{Game, ModDependency};
-use repo::Repo;
+use frangiclave_mod_manager::config::{Config, Install};
+use frangiclave_mod_manager::discovery;
+use frangiclave_mod_manager::graph::{EdgeStatus, ModGraph};
+use frangiclave_mod_manager::lockfile::{InstallReason, Lockfile};
+use frangiclave_mod_manager::{
+    Error, Game, ModDependency, ModUpdate, PatchStatus, Platform, Repo,
+};
+use std::fs;
 use std::io::{Read, Write};
 use std::path::PathBuf;
+use std::process;
 
 const LOGO: &'static str = r#"
    __                       _      _
@@ -32,44 +27,551 @@ const LOGO: &'static str = r#"
                       |___/
 "#;
 
+#[derive(Serialize)]
+struct ModListing<'a> {
+    id: &'a str,
+    name: &'a str,
+    author: &'a str,
+    version: String,
+    dependencies: Vec<String>,
+    optional_dependencies: Vec<String>,
+    incompatible: Vec<String>,
+    game_version: Option<String>,
+    reason: InstallReason,
+    enabled: bool,
+}
+
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_GAME_NOT_FOUND: i32 = 2;
+
 fn main() {
     // Parse the command-line arguments
     let arguments = clap::App::new("Frangiclave")
+        .version(env!("CARGO_PKG_VERSION"))
         .arg(
             clap::Arg::with_name("game_directory")
                 .short("g")
                 .long("game_directory")
                 .value_name("GAME_DIRECTORY")
                 .help("Sets the location of the game directory")
-                .takes_value(true),
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("game")
+                .long("game")
+                .value_name("NAME")
+                .help("Uses the game installation registered under this name")
+                .conflicts_with("game_directory")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            clap::Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("Sets the platform the game was built for, instead of detecting it")
+                .possible_values(&["windows", "macos", "linux"])
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("status")
+                .about("Shows whether Cultist Simulator is currently patched"),
+        )
+        .subcommand(clap::SubCommand::with_name("patch").about("Patches Cultist Simulator"))
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about("Restores the unpatched version of Cultist Simulator")
+                .arg(
+                    clap::Arg::with_name("remove_mods")
+                        .long("remove-mods")
+                        .help("Also removes the mods folder and every mod in it"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("install")
+                .about("Installs one or more mods")
+                .arg(
+                    clap::Arg::with_name("dependency")
+                        .value_name("DEPENDENCY")
+                        .help("Mod to install, optionally with a version (e.g. \"mod ^1.2\")")
+                        .required_unless("locked")
+                        .conflicts_with("locked")
+                        .multiple(true),
+                )
+                .arg(
+                    clap::Arg::with_name("locked")
+                        .long("locked")
+                        .help("Installs exactly the mods recorded in the lockfile"),
+                )
+                .arg(
+                    clap::Arg::with_name("force")
+                        .long("force")
+                        .help("Installs mods even if they don't support this version of the game"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("remove")
+                .about("Removes one or more mods")
+                .arg(
+                    clap::Arg::with_name("mod_id")
+                        .value_name("MOD_ID")
+                        .help("ID of the mod to remove")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    clap::Arg::with_name("cascade")
+                        .long("cascade")
+                        .help("Also removes the mods that depend on the removed mods"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("enable")
+                .about("Enables one or more disabled mods, along with their disabled dependencies")
+                .arg(
+                    clap::Arg::with_name("mod_id")
+                        .value_name("MOD_ID")
+                        .help("ID of the mod to enable")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    clap::Arg::with_name("force")
+                        .long("force")
+                        .help("Enables mods even if they don't support this version of the game"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("disable")
+                .about("Disables one or more mods without removing them")
+                .arg(
+                    clap::Arg::with_name("mod_id")
+                        .value_name("MOD_ID")
+                        .help("ID of the mod to disable")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    clap::Arg::with_name("cascade")
+                        .long("cascade")
+                        .help("Also disables the mods that depend on the disabled mods"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("autoremove")
+                .about("Removes mods that were installed as dependencies and are no longer needed"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("update")
+                .about("Updates installed mods")
+                .arg(
+                    clap::Arg::with_name("force")
+                        .long("force")
+                        .help("Installs mods even if they don't support this version of the game"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("list")
+                .about("Lists installed mods")
+                .arg(
+                    clap::Arg::with_name("json")
+                        .long("json")
+                        .help("Prints the list of mods as JSON"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("tree")
+                .about("Shows the dependencies of installed mods as a tree")
+                .arg(
+                    clap::Arg::with_name("mod_id")
+                        .value_name("MOD_ID")
+                        .help("ID of the mod whose dependencies to show, instead of every mod"),
+                )
+                .arg(
+                    clap::Arg::with_name("dot")
+                        .long("dot")
+                        .help("Prints the dependency graph in the DOT language of Graphviz"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("why")
+                .about("Shows which installed mods depend on a mod")
+                .arg(
+                    clap::Arg::with_name("mod_id")
+                        .value_name("MOD_ID")
+                        .help("ID of the mod")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("game")
+                .about("Manages the registered game installations")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("add")
+                        .about("Registers a game installation under a name")
+                        .arg(
+                            clap::Arg::with_name("name")
+                                .value_name("NAME")
+                                .help("Name of the installation")
+                                .required(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("path")
+                                .value_name("PATH")
+                                .help("Location of the game directory")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("remove")
+                        .about("Unregisters a game installation")
+                        .arg(
+                            clap::Arg::with_name("name")
+                                .value_name("NAME")
+                                .help("Name of the installation")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("default")
+                        .about("Sets the game installation to use by default")
+                        .arg(
+                            clap::Arg::with_name("name")
+                                .value_name("NAME")
+                                .help("Name of the installation")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("Lists the registered game installations"),
+                ),
         )
         .get_matches();
-    let game_directory = arguments.value_of("game_directory").unwrap_or(".");
-    let mut search_path = PathBuf::from(game_directory);
-    show_welcome_message();
+    let game_directory = arguments.value_of("game_directory");
+    let game_name = arguments.value_of("game");
+    let platform: Option<Platform> = arguments.value_of("platform").map(|p| p.parse().unwrap());
+    let mut config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("WARNING: Failed to load configuration: {}", e);
+            Config::default()
+        }
+    };
 
-    // Try to locate the root of the game directory if we're not already there, then load the game
-    // directory information.
-    loop {
-        let game = Game::new(&search_path);
-        if game.is_valid() {
-            command_loop(&game);
-            break;
-        } else {
-            search_path = match search_path.parent() {
-                Some(parent) => parent,
+    // Without a subcommand, run the interactive command loop; otherwise, run the requested
+    // command once and report its outcome through the exit code.
+    let exit_code = match arguments.subcommand() {
+        ("game", Some(subcommand_arguments)) => {
+            if manage_installs(&mut config, platform, subcommand_arguments) {
+                EXIT_SUCCESS
+            } else {
+                EXIT_FAILURE
+            }
+        }
+        (name, Some(subcommand_arguments)) => {
+            match locate_game(game_directory, game_name, platform, &config, false) {
+                Some(game) => {
+                    if run_subcommand(&game, name, subcommand_arguments) {
+                        EXIT_SUCCESS
+                    } else {
+                        EXIT_FAILURE
+                    }
+                }
+                None => {
+                    eprintln!("ERROR: Cultist Simulator not detected.");
+                    EXIT_GAME_NOT_FOUND
+                }
+            }
+        }
+        _ => {
+            show_welcome_message();
+            match locate_game(game_directory, game_name, platform, &config, true) {
+                Some(game) => {
+                    command_loop(&game);
+                    EXIT_SUCCESS
+                }
                 None => {
                     eprintln!(
-                        "ERROR: Cultist Simulator not detected in directory. This program must be \
-                         run from somewhere within your game's directory."
+                        "ERROR: Cultist Simulator not detected. This program must be run from \
+                         somewhere within your game's directory, or be given its location with -g."
                     );
                     println!("Press Enter key to exit...");
                     let mut stdin = std::io::stdin();
                     stdin.read(&mut [0u8]).unwrap();
-                    break;
+                    EXIT_GAME_NOT_FOUND
+                }
+            }
+        }
+    };
+    process::exit(exit_code);
+}
+
+/// Finds the game in the given directory or registered installation, or, if none was given, in the
+/// current directory, the default installation, or among the installations found on this system.
+/// If several are found, the user is asked to choose one when running interactively.
+fn locate_game(
+    game_directory: Option<&str>,
+    game_name: Option<&str>,
+    platform: Option<Platform>,
+    config: &Config,
+    interactive: bool,
+) -> Option<Game> {
+    if let Some(d) = game_directory {
+        return search_game_directory(d, platform);
+    }
+    if let Some(name) = game_name {
+        return match config.installs.get(name) {
+            Some(install) => load_install(install, platform),
+            None => {
+                eprintln!("No game installation is registered as '{}'.", name);
+                None
+            }
+        };
+    }
+    if let Some(game) = search_game_directory(".", platform) {
+        return Some(game);
+    }
+    if let Some(install) = config.get_default_install() {
+        return load_install(install, platform);
+    }
+    let known_installs: Vec<PathBuf> = config
+        .known_installs
+        .iter()
+        .cloned()
+        .chain(config.installs.values().map(|i| i.path.clone()))
+        .collect();
+    let installs = discovery::find_installs(&known_installs);
+    let install = match installs.len() {
+        0 => return None,
+        1 => &installs[0],
+        _ => {
+            if interactive {
+                pick_install(&installs)?
+            } else {
+                eprintln!("Several installations of Cultist Simulator were found:");
+                for install in installs.iter() {
+                    eprintln!("    {}", install.display());
                 }
-            }.to_owned();
+                eprintln!("Choose one of them with -g.");
+                return None;
+            }
         }
+    };
+    let game = load_game(install, platform);
+    if game.is_valid() {
+        Some(game)
+    } else {
+        None
+    }
+}
+
+/// Asks the user to choose one of several installations of the game.
+fn pick_install(installs: &[PathBuf]) -> Option<&PathBuf> {
+    println!("Several installations of Cultist Simulator were found:");
+    for (i, install) in installs.iter().enumerate() {
+        println!("[{}] {}", i + 1, install.display());
+    }
+
+    let mut stdout = std::io::stdout();
+    let stdin = std::io::stdin();
+    loop {
+        print!("Choose an installation: ");
+        stdout.flush().unwrap();
+        let mut choice = String::new();
+        if stdin.read_line(&mut choice).unwrap() == 0 {
+            return None;
+        }
+        match choice.trim().parse::<usize>() {
+            Ok(i) if i >= 1 && i <= installs.len() => return Some(&installs[i - 1]),
+            _ => eprintln!(
+                "Invalid choice '{}', must be a number between 1 and {}",
+                choice.trim(),
+                installs.len()
+            ),
+        }
+    }
+}
+
+/// Tries to locate the root of the game directory if we're not already there, then loads the game
+/// directory information.
+fn search_game_directory(game_directory: &str, platform: Option<Platform>) -> Option<Game> {
+    let mut search_path =
+        fs::canonicalize(game_directory).unwrap_or_else(|_| PathBuf::from(game_directory));
+    loop {
+        let game = load_game(&search_path, platform);
+        if game.is_valid() {
+            return Some(game);
+        }
+        search_path = match search_path.parent() {
+            Some(parent) => parent.to_owned(),
+            None => return None,
+        };
+    }
+}
+
+fn load_game(root: &PathBuf, platform: Option<Platform>) -> Game {
+    match platform {
+        Some(p) => Game::with_platform(root, p),
+        None => Game::new(root),
+    }
+}
+
+/// Loads a registered installation, with the platform it was registered with unless another one
+/// is given.
+fn load_install(install: &Install, platform: Option<Platform>) -> Option<Game> {
+    let game = match platform {
+        Some(p) => Game::with_platform(&install.path, p),
+        None => install.game(),
+    };
+    if game.is_valid() {
+        Some(game)
+    } else {
+        eprintln!(
+            "The game installation at {} could not be found.",
+            install.path.display()
+        );
+        None
+    }
+}
+
+fn manage_installs(
+    config: &mut Config,
+    platform: Option<Platform>,
+    arguments: &clap::ArgMatches,
+) -> bool {
+    match arguments.subcommand() {
+        ("add", Some(arguments)) => {
+            let name = arguments.value_of("name").unwrap();
+            let path = arguments.value_of("path").unwrap();
+            add_install(config, name, path, platform)
+        }
+        ("remove", Some(arguments)) => {
+            let name = arguments.value_of("name").unwrap();
+            if !config.remove_install(name) {
+                eprintln!("No game installation is registered as '{}'.", name);
+                return false;
+            }
+            if !save_config(config) {
+                return false;
+            }
+            println!("Successfully removed {}", name);
+            true
+        }
+        ("default", Some(arguments)) => {
+            let name = arguments.value_of("name").unwrap();
+            if !config.set_default_install(name) {
+                eprintln!("No game installation is registered as '{}'.", name);
+                return false;
+            }
+            if !save_config(config) {
+                return false;
+            }
+            println!("{} is now the default game installation.", name);
+            true
+        }
+        ("list", Some(_)) => list_installs(config),
+        _ => unreachable!(),
+    }
+}
+
+fn add_install(config: &mut Config, name: &str, path: &str, platform: Option<Platform>) -> bool {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let game = load_game(&path, platform);
+    if !game.is_valid() {
+        eprintln!("Cultist Simulator not detected in {}.", path.display());
+        return false;
+    }
+    config.add_install(
+        name,
+        Install {
+            path,
+            platform: game.platform(),
+        },
+    );
+    if !save_config(config) {
+        return false;
+    }
+    println!("Successfully added {} ({})", name, game.root().display());
+    true
+}
+
+fn list_installs(config: &Config) -> bool {
+    if config.installs.is_empty() {
+        println!("No game installations registered.");
+    }
+    for (name, install) in config.installs.iter() {
+        let marker = if config.default_install.as_ref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        let game = install.game();
+        let status = if !game.is_valid() {
+            "not found".to_string()
+        } else {
+            match game.patch_status() {
+                Ok(PatchStatus::Vanilla) => "not patched".to_string(),
+                Ok(PatchStatus::Patched) => "patched".to_string(),
+                Ok(PatchStatus::PatchOutdated) => "patch outdated".to_string(),
+                Ok(PatchStatus::GameUpdatedSinceBackup) => "game updated since patch".to_string(),
+                Err(e) => format!("unknown ({})", e),
+            }
+        };
+        println!("{} {}", marker, name);
+        println!("    Path: {}", install.path.display());
+        println!("    Layout: {}", install.platform);
+        println!("    Status: {}", status);
+    }
+    true
+}
+
+fn save_config(config: &Config) -> bool {
+    match config.save() {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("There was an error saving the configuration: {}", e);
+            false
+        }
+    }
+}
+
+fn run_subcommand(game: &Game, name: &str, arguments: &clap::ArgMatches) -> bool {
+    match name {
+        "status" => show_status(game),
+        "patch" => patch_game(game),
+        "restore" => restore_game(game, arguments.is_present("remove_mods")),
+        "install" => {
+            let force = arguments.is_present("force");
+            if arguments.is_present("locked") {
+                install_locked_mods(game, force)
+            } else {
+                let dependencies: Vec<&str> = arguments.values_of("dependency").unwrap().collect();
+                install_mods(game, &dependencies, force)
+            }
+        }
+        "remove" => {
+            let mod_ids: Vec<&str> = arguments.values_of("mod_id").unwrap().collect();
+            remove_mods(game, &mod_ids, arguments.is_present("cascade"))
+        }
+        "enable" => {
+            let mod_ids: Vec<&str> = arguments.values_of("mod_id").unwrap().collect();
+            enable_mods(game, &mod_ids, arguments.is_present("force"))
+        }
+        "disable" => {
+            let mod_ids: Vec<&str> = arguments.values_of("mod_id").unwrap().collect();
+            disable_mods(game, &mod_ids, arguments.is_present("cascade"))
+        }
+        "autoremove" => autoremove_mods(game),
+        "update" => update_mods(game, arguments.is_present("force")),
+        "list" => list_mods(game, arguments.is_present("json")),
+        "tree" => show_tree(game, arguments.value_of("mod_id"), arguments.is_present("dot")),
+        "why" => show_dependents(game, arguments.value_of("mod_id").unwrap()),
+        _ => unreachable!(),
     }
 }
 
@@ -81,10 +583,18 @@ fn show_welcome_message() {
 fn command_loop(game: &Game) {
     // Show a list of possible actions the first time
     println!("Choose an action to perform:");
+    println!("[s] Show patch status");
     println!("[p] Patch Cultist Simulator");
+    println!("[v] Restore vanilla Cultist Simulator");
     println!("[i] Install mod");
     println!("[u] Update mods");
     println!("[r] Remove mod");
+    println!("[e] Enable mod");
+    println!("[d] Disable mod");
+    println!("[a] Remove unneeded dependencies");
+    println!("[l] List installed mods");
+    println!("[t] Show dependency tree");
+    println!("[w] Show why a mod is installed");
     println!("[x] Exit");
 
     let mut stdout = std::io::stdout();
@@ -97,13 +607,94 @@ fn command_loop(game: &Game) {
         command = command.trim().to_string();
         match command.chars().next() {
             Some(c) => match c {
-                'p' => patch_game(game),
-                'i' => install_mod(game, command.as_ref()),
-                'u' => update_mods(),
-                'r' => remove_mod(game, command.as_ref()),
+                's' => {
+                    show_status(game);
+                }
+                'p' => {
+                    patch_game(game);
+                }
+                'v' => {
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() > 2 || (args.len() == 2 && args[1] != "--remove-mods") {
+                        eprintln!("Invalid arguments specified. Usage: v [--remove-mods]");
+                    } else {
+                        restore_game(game, args.len() == 2);
+                    }
+                }
+                'i' => {
+                    // Get the dependency as the rest of the line, optionally followed by --force
+                    let mut dependency = command[1..].trim();
+                    let force = dependency.ends_with(" --force");
+                    if force {
+                        dependency = dependency[..dependency.len() - "--force".len()].trim();
+                    }
+                    if dependency.is_empty() || !command[1..].starts_with(' ') {
+                        eprintln!("Invalid arguments specified. Usage: i <dependency> [--force]");
+                    } else {
+                        install_mods(game, &[dependency], force);
+                    }
+                }
+                'u' => {
+                    update_mods(game, false);
+                }
+                'r' => {
+                    // Get the mod ID as the only argument, optionally followed by --cascade
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() < 2 || args.len() > 3 || (args.len() == 3 && args[2] != "--cascade")
+                    {
+                        eprintln!("Invalid arguments specified. Usage: r <mod_id> [--cascade]");
+                    } else {
+                        remove_mods(game, &args[1..2], args.len() == 3);
+                    }
+                }
+                'e' => {
+                    // Get the mod ID as the only argument, optionally followed by --force
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() < 2
+                        || args.len() > 3
+                        || (args.len() == 3 && args[2] != "--force")
+                    {
+                        eprintln!("Invalid arguments specified. Usage: e <mod_id> [--force]");
+                    } else {
+                        enable_mods(game, &args[1..2], args.len() == 3);
+                    }
+                }
+                'd' => {
+                    // Get the mod ID as the only argument, optionally followed by --cascade
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() < 2 || args.len() > 3 || (args.len() == 3 && args[2] != "--cascade")
+                    {
+                        eprintln!("Invalid arguments specified. Usage: d <mod_id> [--cascade]");
+                    } else {
+                        disable_mods(game, &args[1..2], args.len() == 3);
+                    }
+                }
+                'a' => {
+                    autoremove_mods(game);
+                }
+                'l' => {
+                    list_mods(game, false);
+                }
+                't' => {
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() > 2 {
+                        eprintln!("Invalid arguments specified. Usage: t [mod_id]");
+                    } else {
+                        show_tree(game, args.get(1).cloned(), false);
+                    }
+                }
+                'w' => {
+                    let args: Vec<&str> = command.split(' ').collect();
+                    if args.len() != 2 {
+                        eprintln!("Invalid arguments specified. Usage: w <mod_id>");
+                    } else {
+                        show_dependents(game, args[1]);
+                    }
+                }
                 'x' => break,
                 _ => eprintln!(
-                    "Invalid command name '{}', must be one of the following: p, i, u, r, x",
+                    "Invalid command name '{}', must be one of the following: \
+                     s, p, v, i, u, r, e, d, a, l, t, w, x",
                     command
                 ),
             },
@@ -113,8 +704,39 @@ fn command_loop(game: &Game) {
     }
 }
 
-fn patch_game(game: &Game) {
+fn show_status(game: &Game) -> bool {
+    match game.version() {
+        Ok(Some(version)) => println!("Cultist Simulator version: {}", version),
+        Ok(None) => println!("Cultist Simulator version: unknown"),
+        Err(e) => eprintln!("WARNING: {}", e),
+    }
+    let status = match game.patch_status() {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("There was an error checking the patch status: {}", e);
+            return false;
+        }
+    };
+    match status {
+        PatchStatus::Vanilla => println!("Cultist Simulator is not patched."),
+        PatchStatus::Patched => println!("Cultist Simulator is patched with the latest patch."),
+        PatchStatus::PatchOutdated => println!(
+            "Cultist Simulator is patched with an older version of the patch. Patch it again to \
+             update it."
+        ),
+        PatchStatus::GameUpdatedSinceBackup => println!(
+            "Cultist Simulator was updated since it was last patched. Patch it again for mods to \
+             work."
+        ),
+    }
+    true
+}
+
+fn patch_game(game: &Game) -> bool {
     let mut stdout = std::io::stdout();
+    if let Ok(PatchStatus::GameUpdatedSinceBackup) = game.patch_status() {
+        println!("Cultist Simulator was updated since it was last patched, backing it up again.");
+    }
     print!("Applying latest patch to Cultist Simulator...");
     stdout.flush().unwrap();
     match game.patch_assembly() {
@@ -122,7 +744,7 @@ fn patch_game(game: &Game) {
         Err(e) => {
             println!(" [ERROR]");
             eprintln!("There was an error patching the game assembly: {}", e);
-            return;
+            return false;
         }
     }
     print!("Creating mods folder...");
@@ -132,58 +754,521 @@ fn patch_game(game: &Game) {
         Err(e) => {
             println!(" [ERROR]");
             eprintln!("There was an error creating the mods folder: {}", e);
-            return;
+            return false;
         }
     }
     println!("Patch successful.");
     println!("You will need to run this command again whenever Cultist Simulator is updated.");
+    true
 }
 
-fn install_mod(game: &Game, command: &str) {
-    // Get the mod ID as the only argument
-    let args: Vec<&str> = command.split(' ').collect();
-    if args.len() != 2 {
-        eprintln!("Invalid number of arguments specified. Usage: i <mod_id>");
-        return;
+fn restore_game(game: &Game, remove_mods: bool) -> bool {
+    let mut stdout = std::io::stdout();
+    print!("Restoring unpatched Cultist Simulator...");
+    stdout.flush().unwrap();
+    match game.restore_assembly(remove_mods) {
+        Ok(_) => {
+            println!(" [OK]");
+            true
+        }
+        Err(e) => {
+            println!(" [ERROR]");
+            eprintln!("There was an error restoring the game assembly: {}", e);
+            false
+        }
     }
-    let mod_dependency = args[1];
+}
 
-    // Initialize the repo and install the mod for it
-    let repo = match Repo::new() {
+fn install_mods(game: &Game, mod_dependencies: &[&str], force: bool) -> bool {
+    // Initialize the repo and install every requested mod with it
+    let mut repo = match Repo::new() {
         Ok(r) => r,
         Err(e) => {
             eprintln!("Failed to prepare repository: {}", e);
-            return;
+            return false;
         }
     };
-    let dependency = match ModDependency::parse(mod_dependency) {
-        Ok(d) => d,
+    repo.set_force(force);
+    let mut dependencies = Vec::new();
+    for mod_dependency in mod_dependencies {
+        match ModDependency::parse(mod_dependency) {
+            Ok(d) => dependencies.push(d),
+            Err(e) => {
+                eprintln!("{}", e);
+                return false;
+            }
+        }
+    }
+    match repo.install_mods(game, dependencies) {
+        Ok(updates) => {
+            if updates.is_empty() {
+                println!("All requested mods are already installed.");
+            }
+            show_updates(&updates);
+            true
+        }
         Err(e) => {
-            eprintln!("Invalid dependency specifier: {}", e);
-            return;
+            eprintln!("There was an error installing the mods: {}", e);
+            show_force_hint(&e, "install");
+            false
+        }
+    }
+}
+
+fn install_locked_mods(game: &Game, force: bool) -> bool {
+    let mut repo = match Repo::new() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Failed to prepare repository: {}", e);
+            return false;
         }
     };
-    match repo.install_mod(game, &dependency) {
-        Ok(_) => println!("Successfully installed {}", mod_dependency),
-        Err(e) => eprintln!("There was an error installing the mod: {}", e),
+    repo.set_force(force);
+    match repo.install_locked(game) {
+        Ok(updates) => {
+            if updates.is_empty() {
+                println!("The installed mods already match the lockfile.");
+            }
+            show_updates(&updates);
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error installing the locked mods: {}", e);
+            show_force_hint(&e, "install");
+            false
+        }
+    }
+}
+
+fn update_mods(game: &Game, force: bool) -> bool {
+    let mut repo = match Repo::new() {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("Failed to prepare repository: {}", e);
+            return false;
+        }
     };
+    repo.set_force(force);
+    match repo.update_mods(game) {
+        Ok(updates) => {
+            if updates.is_empty() {
+                println!("All mods are up to date.");
+            }
+            show_updates(&updates);
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error updating the mods: {}", e);
+            show_force_hint(&e, "install");
+            false
+        }
+    }
+}
+
+fn show_updates(updates: &[ModUpdate]) {
+    for update in updates {
+        match (&update.old_version, &update.new_version) {
+            (None, Some(new_version)) => println!("Installed {} {}", update.id, new_version),
+            (Some(old_version), None) => println!("Removed {} {}", update.id, old_version),
+            (Some(old_version), Some(new_version)) if old_version == new_version => {
+                println!("Enabled {} {}", update.id, new_version)
+            }
+            (Some(old_version), Some(new_version)) if old_version > new_version => println!(
+                "Downgraded {} from {} to {}",
+                update.id, old_version, new_version
+            ),
+            (Some(old_version), Some(new_version)) => println!(
+                "Updated {} from {} to {}",
+                update.id, old_version, new_version
+            ),
+            (None, None) => (),
+        }
+    }
+}
+
+fn show_force_hint(error: &Error, action: &str) {
+    if let Error::IncompatibleGameVersion { .. } = error {
+        eprintln!("Use --force to {} it anyway.", action);
+    }
+}
+
+fn remove_mods(game: &Game, mod_ids: &[&str], cascade: bool) -> bool {
+    match game.remove_mods(mod_ids, cascade) {
+        Ok(removed_ids) => {
+            for removed_id in removed_ids {
+                println!("Successfully removed {}", removed_id);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error removing the mods: {}", e);
+            if let Error::HasDependents { .. } = e {
+                eprintln!("Use --cascade to remove them too.");
+            }
+            false
+        }
+    }
 }
 
-fn update_mods() {
-    println!("Updating mods is not implemented yet.");
+fn enable_mods(game: &Game, mod_ids: &[&str], force: bool) -> bool {
+    match game.enable_mods(mod_ids, force) {
+        Ok(enabled_mods) => {
+            if enabled_mods.is_empty() {
+                println!("All requested mods are already enabled.");
+            }
+            for enabled_mod in enabled_mods {
+                println!("Successfully enabled {}", enabled_mod.id);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error enabling the mods: {}", e);
+            show_force_hint(&e, "enable");
+            false
+        }
+    }
 }
 
-fn remove_mod(game: &Game, command: &str) {
-    // Get the mod ID as the only argument
-    let args: Vec<&str> = command.split(' ').collect();
-    if args.len() != 2 {
-        eprintln!("Invalid number of arguments specified. Usage: r <mod_id>");
-        return;
+fn disable_mods(game: &Game, mod_ids: &[&str], cascade: bool) -> bool {
+    match game.disable_mods(mod_ids, cascade) {
+        Ok(disabled_mods) => {
+            if disabled_mods.is_empty() {
+                println!("All requested mods are already disabled.");
+            }
+            for disabled_mod in disabled_mods {
+                println!("Successfully disabled {}", disabled_mod.id);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error disabling the mods: {}", e);
+            if let Error::HasDependents { .. } = e {
+                eprintln!("Use --cascade to disable them too.");
+            }
+            false
+        }
     }
-    let mod_id = args[1];
+}
+
+fn autoremove_mods(game: &Game) -> bool {
+    match game.autoremove_mods() {
+        Ok(removed_ids) => {
+            if removed_ids.is_empty() {
+                println!("No unneeded mods to remove.");
+            }
+            for removed_id in removed_ids {
+                println!("Successfully removed {}", removed_id);
+            }
+            true
+        }
+        Err(e) => {
+            eprintln!("There was an error removing the unneeded mods: {}", e);
+            false
+        }
+    }
+}
+
+fn list_mods(game: &Game, json: bool) -> bool {
+    let mut mods = Vec::new();
+    for &enabled in [true, false].iter() {
+        let loaded_mods = if enabled {
+            game.load_mods()
+        } else {
+            game.load_disabled_mods()
+        };
+        match loaded_mods {
+            Ok(loaded_mods) => for loaded_mod in loaded_mods {
+                match loaded_mod {
+                    Ok(m) => mods.push((m, enabled)),
+                    Err(e) => eprintln!("WARNING: {}", e),
+                }
+            },
+            Err(e) => {
+                eprintln!("There was an error reading the mods folder: {}", e);
+                return false;
+            }
+        };
+    }
+    let lockfile = match Lockfile::load(game) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("WARNING: {}", e);
+            Lockfile::default()
+        }
+    };
+
+    if json {
+        let listings: Vec<ModListing> = mods
+            .iter()
+            .map(|(m, enabled)| ModListing {
+                id: &m.id,
+                name: &m.name,
+                author: &m.author,
+                version: m.version.to_string(),
+                dependencies: m.dependencies.iter().map(|d| d.to_string()).collect(),
+                optional_dependencies: m
+                    .optional_dependencies
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect(),
+                incompatible: m.incompatible.iter().map(|d| d.to_string()).collect(),
+                game_version: m.game_version.as_ref().map(|r| r.to_string()),
+                reason: lockfile.reason(&m.id),
+                enabled: *enabled,
+            })
+            .collect();
+        return match serde_json::to_string_pretty(&listings) {
+            Ok(output) => {
+                println!("{}", output);
+                true
+            }
+            Err(e) => {
+                eprintln!("There was an error serializing the list of mods: {}", e);
+                false
+            }
+        };
+    }
+
+    if mods.is_empty() {
+        println!("No mods installed.");
+    }
+    for (installed_mod, enabled) in mods {
+        if enabled {
+            println!("{} {}", installed_mod.id, installed_mod.version);
+        } else {
+            println!("{} {} (disabled)", installed_mod.id, installed_mod.version);
+        }
+        println!("    Name: {}", installed_mod.name);
+        println!("    Author: {}", installed_mod.author);
+        if installed_mod.dependencies.is_empty() {
+            println!("    Dependencies: none");
+        } else {
+            let dependencies: Vec<String> = installed_mod
+                .dependencies
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+            println!("    Dependencies: {}", dependencies.join(", "));
+        }
+        if !installed_mod.optional_dependencies.is_empty() {
+            let dependencies: Vec<String> = installed_mod
+                .optional_dependencies
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+            println!("    Optional dependencies: {}", dependencies.join(", "));
+        }
+        if !installed_mod.incompatible.is_empty() {
+            let incompatible: Vec<String> = installed_mod
+                .incompatible
+                .iter()
+                .map(|d| d.to_string())
+                .collect();
+            println!("    Incompatible with: {}", incompatible.join(", "));
+        }
+        if let Some(requirement) = &installed_mod.game_version {
+            println!("    Game version: {}", requirement);
+        }
+        if lockfile.reason(&installed_mod.id) == InstallReason::Dependency {
+            println!("    Installed as a dependency");
+        }
+    }
+    true
+}
+
+fn show_tree(game: &Game, mod_id: Option<&str>, dot: bool) -> bool {
+    let graph = match ModGraph::load(game) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("There was an error reading the mods folder: {}", e);
+            return false;
+        }
+    };
+    let roots = match mod_id {
+        Some(id) => match graph.get(id) {
+            Some(m) => vec![m],
+            None => {
+                eprintln!("Mod '{}' is not installed.", id);
+                return false;
+            }
+        },
+        None => graph.roots(),
+    };
+    if dot {
+        print!("{}", graph.to_dot(mod_id));
+        return true;
+    }
+
+    if roots.is_empty() {
+        println!("No mods installed.");
+    }
+    let disabled = |id: &str| if graph.is_disabled(id) { " (disabled)" } else { "" };
+    let dependencies = |id: &str| {
+        graph
+            .dependencies(id)
+            .into_iter()
+            .map(|edge| match edge.status {
+                EdgeStatus::Satisfied(m) => (
+                    format!("{} {}{}", m.id, m.version, disabled(&m.id)),
+                    Some(&m.id),
+                ),
+                EdgeStatus::Missing => (format!("{} [missing]", edge.dependency), None),
+                EdgeStatus::Mismatched(m) => (
+                    format!(
+                        "{} {}{} [requires {}]",
+                        m.id,
+                        m.version,
+                        disabled(&m.id),
+                        edge.dependency.requirement
+                    ),
+                    Some(&m.id),
+                ),
+            })
+            .collect()
+    };
+    let mut shown = Vec::new();
+    for root in roots {
+        println!("{} {}{}", root.id, root.version, disabled(&root.id));
+        let mut lines = Vec::new();
+        tree_lines(&root.id, "", &dependencies, &mut vec![&root.id], &mut shown, &mut lines);
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+    true
+}
+
+fn show_dependents(game: &Game, mod_id: &str) -> bool {
+    let graph = match ModGraph::load(game) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("There was an error reading the mods folder: {}", e);
+            return false;
+        }
+    };
+    let lockfile = match Lockfile::load(game) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("WARNING: {}", e);
+            Lockfile::default()
+        }
+    };
+    let requested = |id: &str| {
+        let mut notes = String::new();
+        if lockfile.reason(id) == InstallReason::Explicit {
+            notes.push_str(" (requested)");
+        }
+        if graph.is_disabled(id) {
+            notes.push_str(" (disabled)");
+        }
+        notes
+    };
+
+    match graph.get(mod_id) {
+        Some(m) => println!("{} {}{}", m.id, m.version, requested(&m.id)),
+        None if !graph.dependents(mod_id).is_empty() => println!("{} [missing]", mod_id),
+        None => {
+            eprintln!("Mod '{}' is not installed.", mod_id);
+            return false;
+        }
+    }
+    let dependents = |id: &str| {
+        graph
+            .dependents(id)
+            .into_iter()
+            .map(|edge| {
+                let mut label = format!(
+                    "{} {}{} requires {}",
+                    edge.dependent.id,
+                    edge.dependent.version,
+                    requested(&edge.dependent.id),
+                    edge.dependency
+                );
+                if let EdgeStatus::Mismatched(_) = edge.status {
+                    label.push_str(" [unsatisfied]");
+                }
+                (label, Some(&edge.dependent.id))
+            })
+            .collect()
+    };
+    let mut shown = Vec::new();
+    let mut lines = Vec::new();
+    tree_lines(mod_id, "", &dependents, &mut vec![mod_id], &mut shown, &mut lines);
+    for line in lines {
+        println!("{}", line);
+    }
+    true
+}
+
+/// Draws the branches below a mod, given a function returning the label of each of its children
+/// and, if it should be expanded, its ID. Mods already shown elsewhere are marked with `(*)`, and
+/// mods leading back to one of their parents with `(cycle)`.
+fn tree_lines<'a, F>(
+    mod_id: &str,
+    prefix: &str,
+    children: &F,
+    path: &mut Vec<&'a str>,
+    shown: &mut Vec<&'a str>,
+    lines: &mut Vec<String>,
+) where
+    F: Fn(&str) -> Vec<(String, Option<&'a String>)>,
+{
+    let branches = children(mod_id);
+    for (i, (label, child_id)) in branches.iter().enumerate() {
+        let last = i == branches.len() - 1;
+        let (branch, indent) = if last { ("`-- ", "    ") } else { ("|-- ", "|   ") };
+        match child_id {
+            Some(id) if path.contains(&id.as_str()) => {
+                lines.push(format!("{}{}{} (cycle)", prefix, branch, label))
+            }
+            Some(id) if shown.contains(&id.as_str()) => {
+                lines.push(format!("{}{}{} (*)", prefix, branch, label))
+            }
+            Some(id) => {
+                lines.push(format!("{}{}{}", prefix, branch, label));
+                shown.push(id);
+                path.push(id);
+                let prefix = format!("{}{}", prefix, indent);
+                tree_lines(id, &prefix, children, path, shown, lines);
+                path.pop();
+            }
+            None => lines.push(format!("{}{}{}", prefix, branch, label)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match game.remove_mod(mod_id) {
-        Ok(_) => println!("Successfully removed {}", mod_id),
-        Err(e) => eprintln!("There was an error removing the mod: {}", e),
+    #[test]
+    fn tree_lines_marks_repeated_mods_and_cycles() {
+        let ids: Vec<String> = vec!["a", "b", "c"].into_iter().map(String::from).collect();
+        let children = |id: &str| -> Vec<(String, Option<&String>)> {
+            match id {
+                "root" => vec![
+                    ("a".to_string(), Some(&ids[0])),
+                    ("b".to_string(), Some(&ids[1])),
+                    ("x [missing]".to_string(), None),
+                ],
+                "a" => vec![("c".to_string(), Some(&ids[2]))],
+                "b" => vec![("c".to_string(), Some(&ids[2]))],
+                "c" => vec![("a".to_string(), Some(&ids[0]))],
+                _ => vec![],
+            }
+        };
+        let mut lines = Vec::new();
+        tree_lines("root", "", &children, &mut vec!["root"], &mut Vec::new(), &mut lines);
+        assert_eq!(
+            lines,
+            vec![
+                "|-- a",
+                "|   `-- c",
+                "|       `-- a (cycle)",
+                "|-- b",
+                "|   `-- c (*)",
+                "`-- x [missing]",
+            ]
+        );
     }
 }
@@ -1,12 +1,15 @@
+use sha2::{Digest, Sha256};
+use std::env;
 use std::fs;
 use std::fs::File;
-use std::io;
 use std::io::prelude::*;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use tempdir::TempDir;
 
+use error::{Error, Result};
+
 const PATCH_FILES: [(&str, &[u8]); 6] = [
     (
         "Assembly-CSharp.FrangiclavePatch.mm.dll",
@@ -31,29 +34,47 @@ const PATCH_FILES: [(&str, &[u8]); 6] = [
     ),
 ];
 
-pub fn setup_patch_directory(managed_path: &Path) -> io::Result<TempDir> {
-    let dir = TempDir::new("frangiclave-patch")?;
+/// Files that patching leaves in the game's `Managed/` directory, besides the patched assembly.
+pub const SUPPORT_FILES: [&str; 5] = [
+    "Mono.Cecil.dll",
+    "Mono.Cecil.Mdb.dll",
+    "Mono.Cecil.Pdb.dll",
+    "MonoMod.exe",
+    "MonoMod.Utils.dll",
+];
+
+/// Creates a temporary directory containing the game's DLLs, MonoMod and FrangiclavePatch, ready
+/// for MonoMod to be run in it, using `assembly_path` as the unpatched game assembly.
+pub fn setup_patch_directory(managed_path: &Path, assembly_path: &Path) -> Result<TempDir> {
+    let dir = TempDir::new("frangiclave-patch").map_err(Error::io(env::temp_dir()))?;
 
     // Copy the Cultist Simulator DLLs.
-    for dir_entry in fs::read_dir(managed_path).unwrap() {
-        let path = dir_entry.unwrap().path();
-        let file_name = path.file_name().unwrap().to_str().unwrap();
+    for dir_entry in fs::read_dir(managed_path).map_err(Error::io(managed_path))? {
+        let path = dir_entry.map_err(Error::io(managed_path))?.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
 
-        // Don't copy the backup Assembly-CSharp.dll, as it won't be used.
+        // Don't copy the current or backup Assembly-CSharp.dll, as the unpatched one is copied
+        // afterwards.
         // Also ensure only DLLs are copied, to avoid unnecessary copies.
-        if file_name == "Assembly-CSharp-backup.dll" || !file_name.ends_with(".dll") {
+        if file_name == "Assembly-CSharp.dll"
+            || file_name.starts_with("Assembly-CSharp-backup")
+            || !file_name.ends_with(".dll")
+        {
             continue;
         }
-        fs::copy(&path, dir.path().join(file_name))?;
+        fs::copy(&path, dir.path().join(&file_name)).map_err(Error::io(&path))?;
     }
+    fs::copy(assembly_path, dir.path().join("Assembly-CSharp.dll"))
+        .map_err(Error::io(assembly_path))?;
 
     // Copy the MonoMod files and the actual patch itself.
     // We do this afterwards, in case some older versions of MonoMod's files were already in the
     // game's directory.
     for file in PATCH_FILES.iter() {
-        let mut f = File::create(dir.path().join(file.0))?;
-        f.write_all(file.1)?;
-        f.sync_all()?;
+        let path = dir.path().join(file.0);
+        let mut f = File::create(&path).map_err(Error::io(&path))?;
+        f.write_all(file.1).map_err(Error::io(&path))?;
+        f.sync_all().map_err(Error::io(&path))?;
     }
 
     // Make MonoMod executable for non-Windows systems
@@ -63,10 +84,36 @@ pub fn setup_patch_directory(managed_path: &Path) -> io::Result<TempDir> {
     Ok(dir)
 }
 
+/// Returns a fingerprint identifying the version of the patch bundled with this program.
+pub fn patch_hash() -> String {
+    let mut hasher = Sha256::default();
+    for file in PATCH_FILES.iter() {
+        hasher.input(file.0.as_bytes());
+        hasher.input(file.1);
+    }
+    format!("{:x}", hasher.result())
+}
+
+/// Returns the SHA-256 hash of a file's contents.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut contents = Vec::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_end(&mut contents))
+        .map_err(Error::io(path))?;
+    Ok(hash_bytes(&contents))
+}
+
+/// Returns the SHA-256 hash of some bytes.
+pub fn hash_bytes(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
 #[cfg(unix)]
-fn make_monomod_executable(monomod_exe_path: &Path) -> io::Result<()> {
-    let mut mm_permissions = fs::metadata(monomod_exe_path)?.permissions();
+fn make_monomod_executable(monomod_exe_path: &Path) -> Result<()> {
+    let mut mm_permissions = fs::metadata(monomod_exe_path)
+        .map_err(Error::io(monomod_exe_path))?
+        .permissions();
     mm_permissions.set_mode(0o755);
-    fs::set_permissions(monomod_exe_path, mm_permissions)?;
+    fs::set_permissions(monomod_exe_path, mm_permissions).map_err(Error::io(monomod_exe_path))?;
     Ok(())
 }
@@ -1,16 +1,25 @@
 use fs_extra::dir;
 use fs_extra::dir::CopyOptions;
+use fs_extra::error::ErrorKind;
 use reqwest;
 use reqwest::{Response, StatusCode};
-use semver::Version;
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tempdir::TempDir;
 use zip::ZipArchive;
 
-use game::{Game, Mod, ModDependency, ModDependencyOperator};
+use error::{Error, Result};
+use game;
+use game::{Game, Mod, ModDependency};
+use lockfile::{InstallReason, LockedMod, Lockfile};
+use patch;
+use resolver;
+use resolver::{ModSource, Preference, Requirement, RequirementOrigin};
 
 const DEFAULT_MOD_REPOSITORY_URL: &'static str = "http://mods.thefansus.com/downloads";
 
@@ -19,264 +28,551 @@ struct RepoModVersions {
     versions: Vec<String>,
 }
 
+/// A mod that was installed, removed, changed from one version to another, or enabled again, in
+/// which case both versions are the same.
+pub struct ModUpdate {
+    pub id: String,
+    pub old_version: Option<Version>,
+    pub new_version: Option<Version>,
+}
+
+/// A remote repository from which mods are downloaded.
 pub struct Repo {
     temp_dir: TempDir,
     url: String,
+    force: bool,
 }
 
 impl Repo {
-    pub fn new() -> Result<Repo, String> {
-        let temp_dir;
-        match TempDir::new("frangiclave-mod-repository") {
-            Ok(dir) => temp_dir = dir,
-            Err(e) => {
-                return Err(format!(
-                    "Failed to initialize temporary directory for repo: {}",
-                    e
-                ))
-            }
-        }
+    /// Prepares a connection to the default mod repository.
+    pub fn new() -> Result<Repo> {
+        let temp_dir =
+            TempDir::new("frangiclave-mod-repository").map_err(Error::io(env::temp_dir()))?;
         Ok(Repo {
             temp_dir,
             url: DEFAULT_MOD_REPOSITORY_URL.to_string(),
+            force: false,
         })
     }
 
-    pub fn install_mod(&self, game: &Game, dependency: &ModDependency) -> Result<(), String> {
-        // Check if the mod is already installed, and, if necessary, determine which version to
-        // download
-        // If no requirement is specified, get the latest version
-        match game.get_mod(&dependency.id) {
-            Some(installed_mod) => {
-                // Mod is installed, check if the version is valid
-                match &dependency.version {
-                    Some(version) => {
-                        let valid = match &dependency.operator {
-                            Some(operator) => match operator {
-                                ModDependencyOperator::LessThan => &installed_mod.version < version,
-                                ModDependencyOperator::LessThanOrEqual => {
-                                    &installed_mod.version <= version
-                                }
-                                ModDependencyOperator::GreaterThan => {
-                                    &installed_mod.version > version
-                                }
-                                ModDependencyOperator::GreaterThanOrEqual => {
-                                    &installed_mod.version >= version
-                                }
-                                ModDependencyOperator::Equal => &installed_mod.version == version,
-                            },
-                            None => panic!("Invalid dependency operator"),
-                        };
-                        if valid {
-                            return Ok(());
-                        } else {
-                            return Err(format!(
-                                "Invalid installed version for '{}': {}",
-                                installed_mod.id, installed_mod.version
-                            ));
-                        }
-                    }
-                    None => return Ok(()), // No requirement, all is well
-                }
+    /// Sets whether mods are installed even if they don't support the installed version of the
+    /// game.
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    /// Installs mods matching `dependencies` into the game, along with their own dependencies,
+    /// keeping the installed mods where possible. Returns every mod that was installed or changed.
+    ///
+    /// The requested mods are recorded as such, while their dependencies are recorded as having
+    /// been installed automatically.
+    pub fn install_mods(
+        &self,
+        game: &Game,
+        dependencies: Vec<ModDependency>,
+    ) -> Result<Vec<ModUpdate>> {
+        let requested_ids: Vec<String> = dependencies.iter().map(|d| d.id.clone()).collect();
+        let mut source = RepoSource::new(self, game)?;
+        let mut requirements = source.installed_requirements();
+        requirements.extend(
+            dependencies
+                .into_iter()
+                .map(|d| Requirement::new(RequirementOrigin::Requested, d)),
+        );
+        let installed_versions = source.installed_versions();
+        let resolution = resolver::resolve(
+            &mut source,
+            requirements,
+            &installed_versions,
+            Preference::Installed,
+        )?;
+        self.apply_resolution(game, &source, resolution, &requested_ids)
+    }
+
+    /// Upgrades every installed mod to the newest versions that are consistent with each other.
+    pub fn update_mods(&self, game: &Game) -> Result<Vec<ModUpdate>> {
+        let mut source = RepoSource::new(self, game)?;
+        let requirements = source.upgrade_requirements();
+        let installed_versions = source.installed_versions();
+        let resolution = resolver::resolve(
+            &mut source,
+            requirements,
+            &installed_versions,
+            Preference::Newest,
+        )?;
+        self.apply_resolution(game, &source, resolution, &[])
+    }
+
+    /// Installs the versions of mods chosen by the resolver that aren't installed yet, installing
+    /// dependencies before their dependents.
+    ///
+    /// Disabled mods that were requested, or that a newly installed mod depends on, are enabled
+    /// again rather than downloaded, while other disabled mods stay disabled, even if updated.
+    fn apply_resolution(
+        &self,
+        game: &Game,
+        source: &RepoSource,
+        resolution: BTreeMap<String, Version>,
+        requested_ids: &[String],
+    ) -> Result<Vec<ModUpdate>> {
+        let mut updates = BTreeMap::new();
+        let mut dependencies = BTreeMap::new();
+        let mut needed_ids = requested_ids.to_vec();
+        for (id, version) in resolution {
+            let old_version = source.installed.get(&id).map(|m| m.version.clone());
+            if old_version.as_ref() != Some(&version) {
+                let fetched_mod = &source.fetched[&(id.clone(), version.clone())];
+                needed_ids.extend(fetched_mod.dependencies.iter().map(|d| d.id.clone()));
+                // Optional dependencies that are installed too are installed first.
+                let dependency_ids = fetched_mod
+                    .dependencies
+                    .iter()
+                    .chain(fetched_mod.optional_dependencies.iter())
+                    .map(|d| d.id.clone());
+                dependencies.insert(id.clone(), dependency_ids.collect());
+                updates.insert(
+                    id.clone(),
+                    ModUpdate {
+                        id,
+                        old_version,
+                        new_version: Some(version),
+                    },
+                );
             }
-            None => (), // Mod is not installed, download it
         }
+        let mut ordered_updates = Vec::new();
+        for id in resolver::dependency_order(&dependencies) {
+            let update = updates.remove(&id).unwrap();
+            let enabled = !source.disabled_ids.contains(&id) || needed_ids.contains(&id);
+            let version = update.new_version.as_ref().unwrap();
+            self.install_fetched_mod(game, &update.id, version, enabled)?;
+            ordered_updates.push(update);
+        }
+        self.update_lockfile(game, &self.url, &ordered_updates, requested_ids)?;
+
+        let enabled_ids: Vec<&str> = source
+            .disabled_ids
+            .iter()
+            .filter(|id| needed_ids.contains(id) && !dependencies.contains_key(*id))
+            .map(|id| id.as_str())
+            .collect();
+        for enabled_mod in game.enable_mods(&enabled_ids, self.force)? {
+            ordered_updates.push(ModUpdate {
+                id: enabled_mod.id,
+                old_version: Some(enabled_mod.version.clone()),
+                new_version: Some(enabled_mod.version),
+            });
+        }
+        Ok(ordered_updates)
+    }
 
-        // Download a list of available versions
-        let versions_url = format!("{0}/{1}/{2}", self.url, dependency.id, "versions.json");
-        let available_versions_str: Vec<String> = match get_url(&versions_url) {
-            Ok(mut response) => {
-                let versions: RepoModVersions = response.json().unwrap();
-                versions.versions
+    /// Installs exactly the mods recorded in the game's lockfile, downloading them from the
+    /// repositories they came from and checking their archives and that they are compatible with
+    /// each other, and removes any other mod. Dependencies are installed before their dependents.
+    pub fn install_locked(&self, game: &Game) -> Result<Vec<ModUpdate>> {
+        let lockfile = Lockfile::load(game)?;
+        let installed_mods = game.get_all_mods()?;
+
+        // Download and check everything before changing anything.
+        let mut updates = BTreeMap::new();
+        let mut dependencies = BTreeMap::new();
+        let mut fetched_mods = Vec::new();
+        for locked_mod in lockfile.mods.iter() {
+            let old_version = installed_mods
+                .iter()
+                .find(|m| m.id == locked_mod.id)
+                .map(|m| m.version.clone());
+            if old_version.as_ref() == Some(&locked_mod.version) {
+                continue;
             }
-            Err(e) => return Err(format!("Request to repository failed: {}", e)),
-        };
-        let mut available_versions: Vec<Version> = available_versions_str
+            let source = match &locked_mod.source {
+                Some(source) => source,
+                None => {
+                    return Err(Error::MissingSource {
+                        mod_id: locked_mod.id.clone(),
+                        version: locked_mod.version.clone(),
+                    })
+                }
+            };
+            let fetched_mod = self.fetch_mod_from(source, &locked_mod.id, &locked_mod.version)?;
+            let checksum = patch::hash_file(
+                &self.fetched_zip_path(&locked_mod.id, &locked_mod.version),
+            )?;
+            if locked_mod.checksum.as_ref() != Some(&checksum) {
+                return Err(Error::ChecksumMismatch {
+                    mod_id: locked_mod.id.clone(),
+                    expected: locked_mod.checksum.clone().unwrap_or_default(),
+                    actual: checksum,
+                });
+            }
+            self.check_game_version(game, &fetched_mod)?;
+            let dependency_ids = fetched_mod
+                .dependencies
+                .iter()
+                .chain(fetched_mod.optional_dependencies.iter())
+                .map(|d| d.id.clone());
+            dependencies.insert(locked_mod.id.clone(), dependency_ids.collect());
+            fetched_mods.push(fetched_mod);
+            updates.insert(
+                locked_mod.id.clone(),
+                ModUpdate {
+                    id: locked_mod.id.clone(),
+                    old_version,
+                    new_version: Some(locked_mod.version.clone()),
+                },
+            );
+        }
+        let locked_mods: Vec<&Mod> = installed_mods
             .iter()
-            .map(|v| Version::parse(v).unwrap())
+            .filter(|m| match lockfile.get(&m.id) {
+                Some(locked_mod) => locked_mod.version == m.version,
+                None => false,
+            })
+            .chain(fetched_mods.iter())
             .collect();
-        available_versions.sort_unstable();
-        let version: String = match &dependency.operator {
-            Some(op) => {
-                let dependency_version = dependency.version.clone().unwrap();
-                let chosen_version = match op {
-                    ModDependencyOperator::LessThan => {
-                        get_chosen_version(&available_versions, dependency_version, |v1, v2| {
-                            v1 < v2
-                        })
-                    }
-                    ModDependencyOperator::LessThanOrEqual => {
-                        get_chosen_version(&available_versions, dependency_version, |v1, v2| {
-                            v1 <= v2
-                        })
-                    }
-                    ModDependencyOperator::GreaterThan => {
-                        get_chosen_version(&available_versions, dependency_version, |v1, v2| {
-                            v1 > v2
-                        })
-                    }
-                    ModDependencyOperator::GreaterThanOrEqual => {
-                        get_chosen_version(&available_versions, dependency_version, |v1, v2| {
-                            v1 >= v2
-                        })
-                    }
-                    ModDependencyOperator::Equal => {
-                        get_chosen_version(&available_versions, dependency_version, |v1, v2| {
-                            v1 == v2
-                        })
-                    }
+        game::check_compatibility(&locked_mods)?;
+        let mut ordered_updates: Vec<ModUpdate> = resolver::dependency_order(&dependencies)
+            .iter()
+            .map(|id| updates.remove(id).unwrap())
+            .collect();
+        for installed_mod in installed_mods {
+            if lockfile.get(&installed_mod.id).is_none() {
+                ordered_updates.push(ModUpdate {
+                    id: installed_mod.id,
+                    old_version: Some(installed_mod.version),
+                    new_version: None,
+                });
+            }
+        }
+
+        for update in ordered_updates.iter() {
+            // Mods that are disabled stay so.
+            let enabled = !game.get_disabled_mods_dir().join(&update.id).exists();
+            match &update.new_version {
+                Some(version) => self.install_fetched_mod(game, &update.id, version, enabled)?,
+                None => game.remove_mod(&update.id)?,
+            }
+        }
+        Ok(ordered_updates)
+    }
+
+    /// Records newly installed mods in the game's lockfile, along with every other change made to
+    /// the installed mods. Mods that weren't installed before are recorded as dependencies, unless
+    /// they were requested.
+    fn update_lockfile(
+        &self,
+        game: &Game,
+        source: &str,
+        updates: &[ModUpdate],
+        requested_ids: &[String],
+    ) -> Result<()> {
+        let mut lockfile = Lockfile::load(game)?;
+        for update in updates {
+            if let Some(version) = &update.new_version {
+                let checksum = patch::hash_file(&self.fetched_zip_path(&update.id, version))?;
+                let reason = match update.old_version {
+                    Some(_) => lockfile.reason(&update.id),
+                    None => InstallReason::Dependency,
                 };
-                match chosen_version {
-                    Some(version) => version.to_string(),
-                    None => {
-                        return Err(format!(
-                            "No valid version found of mod '{}'",
-                            &dependency.id
-                        ))
-                    }
-                }
+                lockfile.lock(LockedMod {
+                    id: update.id.clone(),
+                    version: version.clone(),
+                    source: Some(source.to_string()),
+                    checksum: Some(checksum),
+                    reason,
+                });
             }
-            None => available_versions.last().unwrap().to_string(),
-        };
+        }
+        lockfile.sync(&game.get_all_mods()?);
+        for mod_id in requested_ids {
+            lockfile.mark_explicit(mod_id);
+        }
+        lockfile.save(game)
+    }
 
-        // Download the requested mod's ZIP file
-        let mod_zip = format!("{0}-{1}.zip", dependency.id, version);
-        let mod_url = format!("{0}/{1}/{2}", self.url, dependency.id, &mod_zip);
-        let mod_zip_file = match get_url_to_file(&mod_url, &self.temp_dir.path().join(&mod_zip)) {
-            Ok(f) => f,
-            Err(e) => return Err(format!("Request to repository failed: {}", e)),
-        };
-        let downloaded_mod = unzip_mod(&mod_zip_file, self.temp_dir.path(), &dependency.id);
-
-        // Move the downloaded mod to the game directory, removing any old versions first
-        let source_dir = self.temp_dir.path().join(&dependency.id);
-        let destination_dir = game.get_mods_dir().join(&dependency.id);
-        if destination_dir.exists() {
-            match fs::remove_dir_all(&destination_dir) {
-                Ok(_) => (),
+    fn get_available_versions(&self, mod_id: &str) -> Result<Vec<Version>> {
+        let versions_url = format!("{0}/{1}/{2}", self.url, mod_id, "versions.json");
+        let versions: RepoModVersions = get_url(&versions_url)?.json().map_err(|source| {
+            // Only failures to read the response are worth retrying, not malformed responses.
+            if source.is_serialization() {
+                Error::InvalidResponse {
+                    url: versions_url.clone(),
+                    reason: source.to_string(),
+                }
+            } else {
+                Error::Network {
+                    url: versions_url.clone(),
+                    source,
+                }
+            }
+        })?;
+        let mut available_versions = Vec::new();
+        for version in versions.versions {
+            match Version::parse(&version) {
+                Ok(v) => available_versions.push(v),
                 Err(e) => {
-                    return Err(format!(
-                        "Failed to delete eventual old versions of mod '{}': {}",
-                        dependency.id, e
-                    ))
+                    return Err(Error::InvalidResponse {
+                        url: versions_url,
+                        reason: format!("invalid version '{}': {}", version, e),
+                    })
                 }
-            };
+            }
+        }
+        available_versions.sort_unstable();
+        Ok(available_versions)
+    }
+
+    /// Downloads and extracts a version of a mod into the temporary directory.
+    fn fetch_mod(&self, mod_id: &str, version: &Version) -> Result<Mod> {
+        self.fetch_mod_from(&self.url, mod_id, version)
+    }
+
+    fn fetch_mod_from(&self, url: &str, mod_id: &str, version: &Version) -> Result<Mod> {
+        let mod_url = format!("{0}/{1}/{2}-{3}.zip", url, mod_id, mod_id, version);
+        let mod_zip_path = self.fetched_zip_path(mod_id, version);
+        let mod_zip_file = get_url_to_file(&mod_url, &mod_zip_path)?;
+        let output_dir = self.fetched_mod_dir(mod_id, version);
+        unzip_mod(&mod_zip_file, &mod_zip_path, &output_dir, mod_id)
+    }
+
+    fn fetched_zip_path(&self, mod_id: &str, version: &Version) -> PathBuf {
+        self.temp_dir
+            .path()
+            .join(format!("{0}-{1}.zip", mod_id, version))
+    }
+
+    fn fetched_mod_dir(&self, mod_id: &str, version: &Version) -> PathBuf {
+        self.temp_dir
+            .path()
+            .join(format!("{0}-{1}", mod_id, version))
+    }
+
+    /// Moves a fetched mod to the mods directory, or to the disabled mods directory unless
+    /// `enabled` is set, replacing any other installed version.
+    fn install_fetched_mod(
+        &self,
+        game: &Game,
+        mod_id: &str,
+        version: &Version,
+        enabled: bool,
+    ) -> Result<()> {
+        let source_dir = self.fetched_mod_dir(mod_id, version).join(mod_id);
+        let enabled_dir = game.get_mods_dir().join(mod_id);
+        let disabled_dir = game.get_disabled_mods_dir().join(mod_id);
+        for dir in [&enabled_dir, &disabled_dir].iter() {
+            if dir.exists() {
+                fs::remove_dir_all(dir).map_err(Error::io(dir))?;
+            }
+        }
+        let destination_dir = if enabled { enabled_dir } else { disabled_dir };
+        if let Some(parent) = destination_dir.parent() {
+            fs::create_dir_all(parent).map_err(Error::io(parent))?;
         }
         let mut copy_options = CopyOptions::new();
         copy_options.copy_inside = true;
-        match dir::copy(&source_dir, &destination_dir, &copy_options) {
-            Ok(_) => (),
-            Err(e) => {
-                return Err(format!(
-                    "Failed to copy files for mod '{}': {}",
-                    dependency.id, e
-                ))
-            }
+        dir::copy(&source_dir, &destination_dir, &copy_options).map_err(|e| Error::Io {
+            path: destination_dir.clone(),
+            source: match e.kind {
+                ErrorKind::Io(source) => source,
+                _ => io::Error::new(io::ErrorKind::Other, e),
+            },
+        })?;
+        Ok(())
+    }
+
+    /// Checks that a mod supports the installed version of the game, unless forced not to.
+    fn check_game_version(&self, game: &Game, checked_mod: &Mod) -> Result<()> {
+        if self.force {
+            return Ok(());
         }
+        game.check_game_version(checked_mod)
+    }
+}
 
-        // Download and install every dependency
-        for mod_dependency in downloaded_mod.dependencies {
-            self.install_mod(game, &mod_dependency)?;
+/// The mods available from a repository, as seen from a game in which some are already installed.
+struct RepoSource<'a> {
+    repo: &'a Repo,
+    game: &'a Game,
+    installed: BTreeMap<String, Mod>,
+    disabled_ids: Vec<String>,
+    fetched: BTreeMap<(String, Version), Mod>,
+}
+
+impl<'a> RepoSource<'a> {
+    fn new(repo: &'a Repo, game: &'a Game) -> Result<RepoSource<'a>> {
+        let disabled_ids = game.get_disabled_mods()?.into_iter().map(|m| m.id).collect();
+        let installed = game
+            .get_all_mods()?
+            .into_iter()
+            .map(|m| (m.id.clone(), m))
+            .collect();
+        Ok(RepoSource {
+            repo,
+            game,
+            installed,
+            disabled_ids,
+            fetched: BTreeMap::new(),
+        })
+    }
+
+    fn installed_versions(&self) -> BTreeMap<String, Version> {
+        self.installed
+            .iter()
+            .map(|(id, m)| (id.clone(), m.version.clone()))
+            .collect()
+    }
+
+    /// Returns a version of a mod, from the game if it is installed, or from the repository.
+    fn get_mod(&mut self, mod_id: &str, version: &Version) -> Result<&Mod> {
+        if let Some(installed_mod) = self.installed.get(mod_id) {
+            if &installed_mod.version == version {
+                return Ok(installed_mod);
+            }
         }
+        let key = (mod_id.to_string(), version.clone());
+        if !self.fetched.contains_key(&key) {
+            let fetched_mod = self.repo.fetch_mod(mod_id, version)?;
+            self.fetched.insert(key.clone(), fetched_mod);
+        }
+        Ok(&self.fetched[&key])
+    }
 
-        Ok(())
+    /// Returns requirements keeping every installed mod installed, in any version.
+    fn installed_requirements(&self) -> Vec<Requirement> {
+        self.installed
+            .keys()
+            .map(|id| {
+                let dependency = ModDependency::new(id.clone(), VersionReq::any());
+                Requirement::new(RequirementOrigin::Installed, dependency)
+            })
+            .collect()
+    }
+
+    /// Returns requirements keeping every installed mod installed, in its installed version or a
+    /// newer one.
+    fn upgrade_requirements(&self) -> Vec<Requirement> {
+        self.installed
+            .values()
+            .map(|m| {
+                let requirement = VersionReq::parse(&format!(">= {}", m.version))
+                    .unwrap_or_else(|_| VersionReq::any());
+                let dependency = ModDependency::new(m.id.clone(), requirement);
+                Requirement::new(RequirementOrigin::Installed, dependency)
+            })
+            .collect()
     }
 }
 
-fn get_chosen_version<F>(
-    available_versions: &Vec<Version>,
-    dependency_version: Version,
-    comparator: F,
-) -> Option<Version>
-where
-    F: Fn(&Version, &Version) -> bool,
-{
-    let mut candidate_version: Option<Version> = None;
-    for version in available_versions {
-        if !comparator(version, &dependency_version) {
-            continue;
+impl<'a> ModSource for RepoSource<'a> {
+    fn versions(&mut self, mod_id: &str) -> Result<Vec<Version>> {
+        let mut versions = match self.repo.get_available_versions(mod_id) {
+            Ok(versions) => versions,
+            // Mods installed by hand may not be in the repository at all.
+            Err(Error::HttpStatus { .. }) if self.installed.contains_key(mod_id) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        if let Some(installed_mod) = self.installed.get(mod_id) {
+            if !versions.contains(&installed_mod.version) {
+                versions.push(installed_mod.version.clone());
+            }
         }
-        candidate_version = candidate_version
-            .map(|v| {
-                if comparator(&v, version) {
-                    version.clone()
-                } else {
-                    v
-                }
-            })
-            .or_else(|| Some(version.clone()));
+        Ok(versions)
+    }
+
+    fn dependencies(&mut self, mod_id: &str, version: &Version) -> Result<Vec<ModDependency>> {
+        Ok(self.get_mod(mod_id, version)?.dependencies.clone())
+    }
+
+    fn incompatibilities(
+        &mut self,
+        mod_id: &str,
+        version: &Version,
+    ) -> Result<Vec<ModDependency>> {
+        // Disabled mods aren't loaded, so they only get in the way once they are enabled again,
+        // which checks that they are compatible with the enabled mods.
+        let installed_version = self.installed.get(mod_id).map(|m| &m.version);
+        if self.disabled_ids.iter().any(|id| id == mod_id) && installed_version == Some(version) {
+            return Ok(Vec::new());
+        }
+        Ok(self.get_mod(mod_id, version)?.incompatible.clone())
+    }
+
+    fn check_game_version(&mut self, mod_id: &str, version: &Version) -> Result<()> {
+        // Installed mods stay installed, even if they were forced in.
+        if self.installed.get(mod_id).map(|m| &m.version) == Some(version) {
+            return Ok(());
+        }
+        let repo = self.repo;
+        let game = self.game;
+        repo.check_game_version(game, self.get_mod(mod_id, version)?)
     }
-    candidate_version
 }
 
-fn unzip_mod(file: &File, output_dir: &Path, mod_id: &str) -> Mod {
-    let mut archive = ZipArchive::new(file).unwrap();
+fn unzip_mod(file: &File, zip_path: &Path, output_dir: &Path, mod_id: &str) -> Result<Mod> {
+    let zip_error = |source| Error::Zip {
+        path: zip_path.to_path_buf(),
+        source,
+    };
+    let mut archive = ZipArchive::new(file).map_err(zip_error)?;
 
     // Copy every file and directory from the archive
     for i in 0..archive.len() {
-        let mut file = archive.by_index(i).unwrap();
+        let mut file = archive.by_index(i).map_err(zip_error)?;
         let output_path = output_dir.join(file.sanitized_name());
 
         if (&*file.name()).ends_with('/') {
-            fs::create_dir_all(&output_path).unwrap();
+            fs::create_dir_all(&output_path).map_err(Error::io(&output_path))?;
         } else {
             if let Some(p) = output_path.parent() {
                 if !p.exists() {
-                    fs::create_dir_all(&p).unwrap();
+                    fs::create_dir_all(&p).map_err(Error::io(&p))?;
                 }
             }
-            let mut output_file = fs::File::create(&output_path).unwrap();
-            io::copy(&mut file, &mut output_file).unwrap();
+            let mut output_file = fs::File::create(&output_path).map_err(Error::io(&output_path))?;
+            io::copy(&mut file, &mut output_file).map_err(Error::io(&output_path))?;
         }
     }
 
     // Load the mod from the downloaded files
-    Mod::new(&output_dir.join(mod_id))
+    Ok(Mod::load(&output_dir.join(mod_id))?)
 }
 
-fn get_url_to_file(url: &str, output_path: &Path) -> Result<File, String> {
+fn get_url_to_file(url: &str, output_path: &Path) -> Result<File> {
     let mut response = get_url(url)?;
 
-    // Download the ZIP file to the output path
-    match response.status() {
-        StatusCode::Ok => (),
-        _ => return Err(format!("Failed to fetch '{}'", url)),
-    }
-    let mut output_file = match File::create(output_path) {
-        Ok(mut f) => f,
-        Err(e) => {
-            return Err(format!(
-                "Failed to create file '{}': {}",
-                output_path.display(),
-                e
-            ))
-        }
-    };
-    match io::copy(&mut response, &mut output_file) {
-        Ok(_) => Ok(File::open(output_path).unwrap()),
-        Err(e) => Err(format!(
-            "Failed to download response body for '{}': {}",
-            url, e
-        )),
-    }
+    // Download the whole ZIP file before writing it to the output path, so that an interrupted
+    // download is reported as a network error
+    let mut contents = Vec::new();
+    response
+        .copy_to(&mut contents)
+        .map_err(|source| Error::Network {
+            url: url.to_string(),
+            source,
+        })?;
+    fs::write(output_path, &contents).map_err(Error::io(output_path))?;
+    File::open(output_path).map_err(Error::io(output_path))
 }
 
-fn get_url(url: &str) -> Result<Response, String> {
+fn get_url(url: &str) -> Result<Response> {
+    let network_error = |source| Error::Network {
+        url: url.to_string(),
+        source,
+    };
+
     // Build our own client to work around a bug with GZIP in the library
     // See: https://github.com/seanmonstar/reqwest/issues/328
-    let request = reqwest::ClientBuilder::new()
+    let response = reqwest::ClientBuilder::new()
         .gzip(false)
         .build()
-        .unwrap()
+        .map_err(network_error)?
         .get(url)
-        .send();
-
-    // Download the ZIP file to the output path
-    match request {
-        Ok(response) => match response.status() {
-            StatusCode::Ok => Ok(response),
-            _ => Err(format!("Failed to fetch '{}'", url)),
-        },
-        Err(e) => Err(format!("Failed to fetch '{}': {}", url, e)),
+        .send()
+        .map_err(network_error)?;
+    match response.status() {
+        StatusCode::Ok => Ok(response),
+        status => Err(Error::HttpStatus {
+            url: url.to_string(),
+            status,
+        }),
     }
 }
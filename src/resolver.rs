@@ -0,0 +1,659 @@
+use semver::{Version, VersionReq};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use error::{Error, Result};
+use game::ModDependency;
+
+/// Provides the versions of mods that can be installed, and what each of them depends on.
+pub trait ModSource {
+    /// Returns every version of a mod that could be installed.
+    fn versions(&mut self, mod_id: &str) -> Result<Vec<Version>>;
+
+    /// Returns the dependencies of a version of a mod.
+    fn dependencies(&mut self, mod_id: &str, version: &Version) -> Result<Vec<ModDependency>>;
+
+    /// Returns the mods that can't be installed along with a version of a mod.
+    fn incompatibilities(&mut self, mod_id: &str, version: &Version)
+        -> Result<Vec<ModDependency>>;
+
+    /// Checks that a version of a mod supports the installed version of the game, failing with
+    /// `Error::IncompatibleGameVersion` if it doesn't.
+    fn check_game_version(&mut self, mod_id: &str, version: &Version) -> Result<()>;
+}
+
+/// Which versions of a mod the resolver tries first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preference {
+    /// Keep the installed versions of mods where possible, and pick the newest otherwise.
+    Installed,
+    /// Pick the newest versions of mods.
+    Newest,
+}
+
+/// A constraint placed on the version of a mod.
+pub struct Requirement {
+    pub origin: RequirementOrigin,
+    pub dependency: ModDependency,
+}
+
+/// What placed a constraint on the version of a mod.
+#[derive(PartialEq)]
+pub enum RequirementOrigin {
+    /// The mod was requested by the user.
+    Requested,
+    /// The mod is already installed, and must stay so.
+    Installed,
+    /// The mod is a dependency of a version of another mod.
+    Mod(String, Version),
+}
+
+impl Requirement {
+    pub fn new(origin: RequirementOrigin, dependency: ModDependency) -> Requirement {
+        Requirement { origin, dependency }
+    }
+}
+
+/// A version of a mod that can't be installed along with some versions of another mod.
+struct Incompatibility {
+    mod_id: String,
+    version: Version,
+    dependency: ModDependency,
+}
+
+/// A version of a mod that doesn't support the installed version of the game.
+struct Unsupported {
+    mod_id: String,
+    version: Version,
+    error: Error,
+}
+
+impl fmt::Display for Incompatibility {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} is incompatible with {}",
+            self.mod_id, self.version, self.dependency
+        )
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.origin {
+            RequirementOrigin::Requested => write!(f, "{} was requested", self.dependency),
+            RequirementOrigin::Installed if self.dependency.requirement == VersionReq::any() => {
+                write!(f, "{} is installed", self.dependency.id)
+            }
+            RequirementOrigin::Installed => write!(
+                f,
+                "{} can't be downgraded from its installed version",
+                self.dependency.id
+            ),
+            RequirementOrigin::Mod(id, version) => {
+                write!(f, "{} {} requires {}", id, version, self.dependency)
+            }
+        }
+    }
+}
+
+/// Finds a version of every mod involved in `requirements` and their dependencies, such that
+/// every requirement is satisfied and no two chosen mods are incompatible.
+///
+/// Versions are tried in order of preference, and the search backtracks whenever a choice leads
+/// to a conflict. If no consistent set of versions exists, the first conflict found is reported.
+pub fn resolve<S: ModSource>(
+    source: &mut S,
+    requirements: Vec<Requirement>,
+    installed: &BTreeMap<String, Version>,
+    preference: Preference,
+) -> Result<BTreeMap<String, Version>> {
+    let mut resolver = Resolver {
+        source,
+        installed,
+        preference,
+        available_versions: BTreeMap::new(),
+        incompatibilities: Vec::new(),
+        unsupported: Vec::new(),
+        conflict: None,
+    };
+    let mut requirements = requirements;
+    let mut assignments = BTreeMap::new();
+    if resolver.search(&mut requirements, &mut assignments)? {
+        Ok(assignments)
+    } else {
+        Err(resolver.conflict.unwrap())
+    }
+}
+
+struct Resolver<'a, S: 'a> {
+    source: &'a mut S,
+    installed: &'a BTreeMap<String, Version>,
+    preference: Preference,
+    available_versions: BTreeMap<String, Vec<Version>>,
+    incompatibilities: Vec<Incompatibility>,
+    unsupported: Vec<Unsupported>,
+    conflict: Option<Error>,
+}
+
+impl<'a, S: ModSource> Resolver<'a, S> {
+    /// Chooses a version for the next undecided mod, then for the rest of them, returning whether
+    /// a consistent choice was found.
+    fn search(
+        &mut self,
+        requirements: &mut Vec<Requirement>,
+        assignments: &mut BTreeMap<String, Version>,
+    ) -> Result<bool> {
+        let mod_id = match requirements
+            .iter()
+            .map(|r| &r.dependency.id)
+            .find(|id| !assignments.contains_key(*id))
+        {
+            Some(id) => id.clone(),
+            None => return Ok(true),
+        };
+        let candidates = self.candidates(&mod_id, requirements)?;
+        if candidates.is_empty() {
+            self.record_conflict(&mod_id, requirements, None);
+            return Ok(false);
+        }
+
+        let mut tried = false;
+        for version in candidates {
+            match self.source.check_game_version(&mod_id, &version) {
+                Ok(()) => (),
+                Err(error @ Error::IncompatibleGameVersion { .. }) => {
+                    self.unsupported.push(Unsupported {
+                        mod_id: mod_id.clone(),
+                        version,
+                        error,
+                    });
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            tried = true;
+            let dependencies = self.source.dependencies(&mod_id, &version)?;
+            let previous_len = requirements.len();
+            requirements.extend(dependencies.into_iter().map(|dependency| {
+                let origin = RequirementOrigin::Mod(mod_id.clone(), version.clone());
+                Requirement::new(origin, dependency)
+            }));
+            let incompatibilities = self.source.incompatibilities(&mod_id, &version)?;
+            let previous_incompatibilities_len = self.incompatibilities.len();
+            self.incompatibilities
+                .extend(incompatibilities.into_iter().map(|dependency| Incompatibility {
+                    mod_id: mod_id.clone(),
+                    version: version.clone(),
+                    dependency,
+                }));
+            assignments.insert(mod_id.clone(), version);
+
+            // The new dependencies and incompatibilities may rule out versions that were already
+            // chosen.
+            let conflicting_id = requirements[previous_len..]
+                .iter()
+                .find(|r| match assignments.get(&r.dependency.id) {
+                    Some(v) => !r.dependency.matches(v),
+                    None => false,
+                })
+                .map(|r| r.dependency.id.clone());
+            let incompatible_id = self.incompatibilities[previous_incompatibilities_len..]
+                .iter()
+                .find(|i| match assignments.get(&i.dependency.id) {
+                    Some(v) => i.dependency.matches(v),
+                    None => false,
+                })
+                .map(|i| i.dependency.id.clone());
+            match (conflicting_id, incompatible_id) {
+                (Some(id), _) => {
+                    // If the mod that was already chosen depends on this one, report the cycle.
+                    let cycle = find_path(requirements, &id, &mod_id).map(|mut path| {
+                        path.push(id.clone());
+                        path
+                    });
+                    self.record_conflict(&id, requirements, cycle);
+                }
+                (None, Some(id)) => self.record_conflict(&id, requirements, None),
+                (None, None) => {
+                    if self.search(requirements, assignments)? {
+                        return Ok(true);
+                    }
+                }
+            }
+            assignments.remove(&mod_id);
+            requirements.truncate(previous_len);
+            self.incompatibilities.truncate(previous_incompatibilities_len);
+        }
+        if !tried {
+            self.record_conflict(&mod_id, requirements, None);
+        }
+        Ok(false)
+    }
+
+    /// Returns the versions of a mod that satisfy every requirement on it, that no chosen mod is
+    /// incompatible with and that aren't known not to support the game, most preferred first.
+    fn candidates(&mut self, mod_id: &str, requirements: &[Requirement]) -> Result<Vec<Version>> {
+        if !self.available_versions.contains_key(mod_id) {
+            let versions = self.source.versions(mod_id)?;
+            self.available_versions.insert(mod_id.to_string(), versions);
+        }
+        let mut candidates: Vec<Version> = self.available_versions[mod_id]
+            .iter()
+            .filter(|v| self.is_acceptable(mod_id, v, requirements))
+            .filter(|v| {
+                !self
+                    .unsupported
+                    .iter()
+                    .any(|u| u.mod_id == mod_id && u.version == **v)
+            })
+            .cloned()
+            .collect();
+
+        // Pre-releases are only picked over stable versions if one is already installed.
+        let installed_version = self.installed.get(mod_id);
+        let allow_prerelease = installed_version.iter().any(|v| v.is_prerelease());
+        let keep_installed = self.preference == Preference::Installed;
+        candidates.sort_by_key(|v| {
+            (
+                keep_installed && Some(v) == installed_version,
+                allow_prerelease || !v.is_prerelease(),
+                v.clone(),
+            )
+        });
+        candidates.reverse();
+        candidates.dedup();
+        Ok(candidates)
+    }
+
+    /// Returns whether a version of a mod satisfies every requirement on it, and no chosen mod is
+    /// incompatible with it.
+    fn is_acceptable(&self, mod_id: &str, version: &Version, requirements: &[Requirement]) -> bool {
+        requirements
+            .iter()
+            .filter(|r| r.dependency.id == mod_id)
+            .all(|r| r.dependency.matches(version))
+            && !self
+                .incompatibilities
+                .iter()
+                .any(|i| i.dependency.id == mod_id && i.dependency.matches(version))
+    }
+
+    /// Remembers why no version of a mod could be chosen, unless an earlier conflict was found.
+    fn record_conflict(
+        &mut self,
+        mod_id: &str,
+        requirements: &[Requirement],
+        cycle: Option<Vec<String>>,
+    ) {
+        if self.conflict.is_some() {
+            return;
+        }
+
+        // If a version would have done if it supported the game, report that it doesn't.
+        let unsupported_index = self
+            .unsupported
+            .iter()
+            .enumerate()
+            .filter(|(_, u)| {
+                u.mod_id == mod_id && self.is_acceptable(mod_id, &u.version, requirements)
+            })
+            .max_by_key(|(_, u)| u.version.clone())
+            .map(|(i, _)| i);
+        if let Some(i) = unsupported_index {
+            self.conflict = Some(self.unsupported.swap_remove(i).error);
+            return;
+        }
+
+        let no_versions = match self.available_versions.get(mod_id) {
+            Some(versions) => versions.is_empty(),
+            None => true,
+        };
+        // Installed mods that may be in any version only matter if nothing else is required.
+        let mut reasons: Vec<String> = requirements
+            .iter()
+            .filter(|r| r.dependency.id == mod_id)
+            .filter(|r| {
+                r.origin != RequirementOrigin::Installed
+                    || r.dependency.requirement != VersionReq::any()
+            })
+            .map(|r| r.to_string())
+            .collect();
+        if reasons.is_empty() {
+            reasons.push(format!("{} is installed", mod_id));
+        }
+        reasons.extend(
+            self.incompatibilities
+                .iter()
+                .filter(|i| i.dependency.id == mod_id)
+                .map(|i| i.to_string()),
+        );
+        let reason = if no_versions {
+            "no versions are available".to_string()
+        } else if reasons.len() == 1 {
+            format!("{}, but no available version satisfies it", reasons[0])
+        } else {
+            format!(
+                "{}, but no available version satisfies all of them",
+                reasons.join(" and ")
+            )
+        };
+        self.conflict = Some(match cycle {
+            Some(cycle) => Error::DependencyCycle { cycle, reason },
+            None => Error::VersionResolution {
+                mod_id: mod_id.to_string(),
+                reason,
+            },
+        });
+    }
+}
+
+/// Finds a chain of dependencies leading from one mod to another, through the versions chosen so
+/// far.
+fn find_path(requirements: &[Requirement], from: &str, to: &str) -> Option<Vec<String>> {
+    let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            let mut path = vec![to.to_string()];
+            let mut step = to;
+            while step != from {
+                step = previous[step];
+                path.push(step.to_string());
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for requirement in requirements.iter() {
+            let next = requirement.dependency.id.as_str();
+            match &requirement.origin {
+                RequirementOrigin::Mod(id, _)
+                    if id == current && next != from && !previous.contains_key(next) =>
+                {
+                    previous.insert(next, current);
+                    queue.push_back(next);
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// Orders mods so that each one comes after the mods it depends on, given the IDs of the
+/// dependencies of each mod. IDs that aren't keys of `dependencies` are left out.
+///
+/// Mods are visited in order of their IDs, each after its dependencies. A cycle of mods that
+/// depend on each other is broken where it is first reached: the mod through which it was
+/// entered comes after the rest of the cycle.
+pub fn dependency_order(dependencies: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    fn visit<'a>(
+        mod_id: &'a str,
+        dependencies: &'a BTreeMap<String, Vec<String>>,
+        visited: &mut Vec<&'a str>,
+        order: &mut Vec<String>,
+    ) {
+        if visited.contains(&mod_id) {
+            return;
+        }
+        visited.push(mod_id);
+        if let Some(mod_dependencies) = dependencies.get(mod_id) {
+            for dependency in mod_dependencies {
+                visit(dependency, dependencies, visited, order);
+            }
+        }
+        if dependencies.contains_key(mod_id) {
+            order.push(mod_id.to_string());
+        }
+    }
+
+    let mut visited = Vec::new();
+    let mut order = Vec::new();
+    for mod_id in dependencies.keys() {
+        visit(mod_id, dependencies, &mut visited, &mut order);
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mods described by `(id, version, dependencies)`, where dependencies starting with `!` are
+    /// incompatibilities, and `#` marks versions that don't support the game.
+    struct TestSource(Vec<(&'static str, &'static str, Vec<&'static str>)>);
+
+    impl TestSource {
+        fn get(&self, mod_id: &str, version: &Version) -> &[&'static str] {
+            &self
+                .0
+                .iter()
+                .find(|m| m.0 == mod_id && Version::parse(m.1).unwrap() == *version)
+                .unwrap()
+                .2
+        }
+    }
+
+    impl ModSource for TestSource {
+        fn versions(&mut self, mod_id: &str) -> Result<Vec<Version>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|m| m.0 == mod_id)
+                .map(|m| Version::parse(m.1).unwrap())
+                .collect())
+        }
+
+        fn dependencies(&mut self, mod_id: &str, version: &Version) -> Result<Vec<ModDependency>> {
+            Ok(self
+                .get(mod_id, version)
+                .iter()
+                .filter(|d| !d.starts_with('!') && !d.starts_with('#'))
+                .map(|d| ModDependency::parse(d).unwrap())
+                .collect())
+        }
+
+        fn incompatibilities(
+            &mut self,
+            mod_id: &str,
+            version: &Version,
+        ) -> Result<Vec<ModDependency>> {
+            Ok(self
+                .get(mod_id, version)
+                .iter()
+                .filter(|d| d.starts_with('!'))
+                .map(|d| ModDependency::parse(&d[1..]).unwrap())
+                .collect())
+        }
+
+        fn check_game_version(&mut self, mod_id: &str, version: &Version) -> Result<()> {
+            if !self.get(mod_id, version).contains(&"#") {
+                return Ok(());
+            }
+            Err(Error::IncompatibleGameVersion {
+                mod_id: mod_id.to_string(),
+                requirement: ">= 2020.1.0".to_string(),
+                game_version: Some(Version::new(2019, 1, 0)),
+            })
+        }
+    }
+
+    fn source() -> TestSource {
+        TestSource(vec![
+            ("a", "1.0.0", vec!["lib < 2.0.0"]),
+            ("c", "1.0.0", vec!["lib >= 2.0.0"]),
+            ("lib", "1.0.0", vec![]),
+            ("lib", "1.5.0", vec![]),
+            ("lib", "2.0.0", vec![]),
+            ("lib", "3.0.0-beta", vec![]),
+            ("x", "1.0.0", vec!["y ^1"]),
+            ("x", "2.0.0", vec!["y ^2"]),
+            ("y", "1.0.0", vec![]),
+            ("y", "2.0.0", vec!["z < 1.0.0"]),
+            ("z", "0.5.0", vec![]),
+            ("z", "1.0.0", vec![]),
+            ("w", "1.0.0", vec!["z >= 1.0.0"]),
+            ("p", "1.0.0", vec!["q"]),
+            ("q", "1.0.0", vec!["p"]),
+            ("m", "1.0.0", vec!["n ^1"]),
+            ("n", "1.0.0", vec!["o"]),
+            ("o", "1.0.0", vec!["m ^2"]),
+            ("k", "1.0.0", vec!["!lib"]),
+            ("k", "2.0.0", vec!["!lib >= 2.0.0"]),
+            ("g", "1.0.0", vec![]),
+            ("g", "2.0.0", vec!["#"]),
+            ("h", "1.0.0", vec!["#"]),
+        ])
+    }
+
+    fn resolve_with(
+        requested: &[&str],
+        installed: &[(&str, &str)],
+        preference: Preference,
+    ) -> Result<Vec<String>> {
+        let requirements = requested
+            .iter()
+            .map(|d| {
+                let dependency = ModDependency::parse(d).unwrap();
+                Requirement::new(RequirementOrigin::Requested, dependency)
+            })
+            .collect();
+        let installed = installed
+            .iter()
+            .map(|(id, v)| (id.to_string(), Version::parse(v).unwrap()))
+            .collect();
+        let resolution = resolve(&mut source(), requirements, &installed, preference)?;
+        Ok(resolution
+            .into_iter()
+            .map(|(id, version)| format!("{} {}", id, version))
+            .collect())
+    }
+
+    fn resolve_requested(requested: &[&str]) -> Result<Vec<String>> {
+        resolve_with(requested, &[], Preference::Installed)
+    }
+
+    fn resolution_error(requested: &[&str]) -> String {
+        resolve_requested(requested).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn picks_newest_stable_versions() {
+        assert_eq!(resolve_requested(&["lib"]).unwrap(), vec!["lib 2.0.0"]);
+        assert_eq!(
+            resolve_requested(&["lib >= 3.0.0-beta"]).unwrap(),
+            vec!["lib 3.0.0-beta"]
+        );
+    }
+
+    #[test]
+    fn keeps_installed_versions_unless_updating() {
+        let installed = [("lib", "1.0.0")];
+        assert_eq!(
+            resolve_with(&["lib"], &installed, Preference::Installed).unwrap(),
+            vec!["lib 1.0.0"]
+        );
+        assert_eq!(
+            resolve_with(&["lib"], &installed, Preference::Newest).unwrap(),
+            vec!["lib 2.0.0"]
+        );
+    }
+
+    #[test]
+    fn backtracks_to_older_versions() {
+        // x 2.0.0 needs y 2.0.0, which needs a version of z that w doesn't accept.
+        assert_eq!(
+            resolve_requested(&["w", "x"]).unwrap(),
+            vec!["w 1.0.0", "x 1.0.0", "y 1.0.0", "z 1.0.0"]
+        );
+    }
+
+    #[test]
+    fn explains_conflicts() {
+        assert_eq!(
+            resolution_error(&["a", "c"]),
+            "Failed to resolve a version of mod 'lib': a 1.0.0 requires lib < 2.0.0 and c 1.0.0 \
+             requires lib >= 2.0.0, but no available version satisfies all of them"
+        );
+        assert_eq!(
+            resolution_error(&["lib ^5"]),
+            "Failed to resolve a version of mod 'lib': lib ^5 was requested, but no available \
+             version satisfies it"
+        );
+        assert_eq!(
+            resolution_error(&["missing"]),
+            "Failed to resolve a version of mod 'missing': no versions are available"
+        );
+    }
+
+    #[test]
+    fn avoids_incompatible_versions() {
+        assert_eq!(
+            resolve_requested(&["k", "lib"]).unwrap(),
+            vec!["k 2.0.0", "lib 1.5.0"]
+        );
+        assert_eq!(
+            resolve_with(&["lib", "k"], &[("lib", "2.0.0")], Preference::Installed).unwrap(),
+            vec!["k 2.0.0", "lib 1.5.0"]
+        );
+        assert_eq!(
+            resolution_error(&["k = 1.0.0", "lib"]),
+            "Failed to resolve a version of mod 'lib': lib was requested and k 1.0.0 is \
+             incompatible with lib, but no available version satisfies all of them"
+        );
+    }
+
+    #[test]
+    fn skips_versions_that_do_not_support_the_game() {
+        assert_eq!(resolve_requested(&["g"]).unwrap(), vec!["g 1.0.0"]);
+        for requested in [["g >= 2.0.0"], ["h"]].iter() {
+            match resolve_requested(requested) {
+                Err(Error::IncompatibleGameVersion { mod_id, .. }) => {
+                    assert_eq!(mod_id, requested[0][..1])
+                }
+                result => panic!("expected an incompatible game version, got {:?}", result),
+            }
+        }
+        assert_eq!(
+            resolution_error(&["h ^5"]),
+            "Failed to resolve a version of mod 'h': h ^5 was requested, but no available version \
+             satisfies it"
+        );
+    }
+
+    #[test]
+    fn resolves_dependency_cycles() {
+        assert_eq!(resolve_requested(&["p"]).unwrap(), vec!["p 1.0.0", "q 1.0.0"]);
+        match resolve_requested(&["m"]) {
+            Err(Error::DependencyCycle { cycle, .. }) => {
+                assert_eq!(cycle, vec!["m", "n", "o", "m"])
+            }
+            result => panic!("expected a dependency cycle, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let mut dependencies = BTreeMap::new();
+        dependencies.insert("a".to_string(), vec!["b".to_string(), "x".to_string()]);
+        dependencies.insert("b".to_string(), vec!["c".to_string()]);
+        dependencies.insert("c".to_string(), vec![]);
+        dependencies.insert("0".to_string(), vec!["a".to_string()]);
+        assert_eq!(dependency_order(&dependencies), vec!["c", "b", "a", "0"]);
+    }
+
+    #[test]
+    fn orders_dependency_cycles() {
+        let mut dependencies = BTreeMap::new();
+        dependencies.insert("a".to_string(), vec!["b".to_string()]);
+        dependencies.insert("b".to_string(), vec!["c".to_string()]);
+        dependencies.insert("c".to_string(), vec!["a".to_string()]);
+        dependencies.insert("0".to_string(), vec!["c".to_string()]);
+
+        // The cycle is entered through c, from 0.
+        assert_eq!(dependency_order(&dependencies), vec!["b", "a", "c", "0"]);
+        dependencies.remove("0");
+        assert_eq!(dependency_order(&dependencies), vec!["c", "b", "a"]);
+    }
+}
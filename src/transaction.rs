@@ -0,0 +1,243 @@
+use serde_json;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use error::{Error, Result};
+
+const JOURNAL_DIR: &'static str = ".frangiclave-journal";
+const JOURNAL_FILE: &'static str = "journal.json";
+const STAGED_DIR: &'static str = "staged";
+const REPLACED_DIR: &'static str = "replaced";
+
+/// A set of files to be copied into a directory all at once.
+///
+/// Files are first staged next to the directory, then moved into it. The files they replace are
+/// moved aside and listed in a journal, so that if anything fails, or the program is interrupted,
+/// the directory can be put back exactly as it was.
+pub struct Transaction {
+    dir: PathBuf,
+    journal_dir: PathBuf,
+    journal: Journal,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    file_name: String,
+    replaces_existing: bool,
+}
+
+impl Transaction {
+    /// Starts a new transaction on `dir`, first rolling back any transaction that was interrupted.
+    pub fn begin(dir: &Path) -> Result<Transaction> {
+        recover(dir)?;
+        let journal_dir = dir.join(JOURNAL_DIR);
+        for sub_dir in [STAGED_DIR, REPLACED_DIR].iter() {
+            let path = journal_dir.join(sub_dir);
+            fs::create_dir_all(&path).map_err(Error::io(&path))?;
+        }
+        Ok(Transaction {
+            dir: dir.to_path_buf(),
+            journal_dir,
+            journal: Journal::default(),
+        })
+    }
+
+    /// Stages a copy of `source`, to be moved into the directory when committing.
+    pub fn stage(&mut self, source: &Path) -> Result<()> {
+        let file_name = source.file_name().unwrap().to_string_lossy().into_owned();
+        let staged_path = self.journal_dir.join(STAGED_DIR).join(&file_name);
+        fs::copy(source, &staged_path).map_err(Error::io(source))?;
+        self.journal.entries.push(JournalEntry {
+            replaces_existing: self.dir.join(&file_name).exists(),
+            file_name,
+        });
+        Ok(())
+    }
+
+    /// Moves every staged file into the directory, restoring its previous contents on failure.
+    pub fn commit(self) -> Result<()> {
+        // Write the journal before touching anything, so that an interruption can be recovered.
+        let journal_path = self.journal_dir.join(JOURNAL_FILE);
+        let journal_file = File::create(&journal_path).map_err(Error::io(&journal_path))?;
+        serde_json::to_writer(&journal_file, &self.journal).map_err(Error::json(&journal_path))?;
+        journal_file.sync_all().map_err(Error::io(&journal_path))?;
+
+        if let Err(e) = self.move_staged_files() {
+            return match rollback(&self.dir) {
+                Ok(()) => Err(e),
+                Err(rollback_error) => Err(Error::Rollback {
+                    source: Box::new(e),
+                    rollback_error: Box::new(rollback_error),
+                }),
+            };
+        }
+        fs::remove_dir_all(&self.journal_dir).map_err(Error::io(&self.journal_dir))
+    }
+
+    fn move_staged_files(&self) -> Result<()> {
+        for entry in self.journal.entries.iter() {
+            let path = self.dir.join(&entry.file_name);
+            if entry.replaces_existing {
+                let replaced_path = self.journal_dir.join(REPLACED_DIR).join(&entry.file_name);
+                fs::rename(&path, &replaced_path).map_err(Error::io(&path))?;
+            }
+            let staged_path = self.journal_dir.join(STAGED_DIR).join(&entry.file_name);
+            fs::rename(&staged_path, &path).map_err(Error::io(&path))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Transaction {
+    /// Discards the staged files of a transaction that was never committed. Once the journal is
+    /// written, it is left for `recover` instead.
+    fn drop(&mut self) {
+        if self.journal_dir.exists() && !self.journal_dir.join(JOURNAL_FILE).exists() {
+            let _ = fs::remove_dir_all(&self.journal_dir);
+        }
+    }
+}
+
+/// Rolls back any transaction on `dir` that was interrupted, and cleans up after it.
+pub fn recover(dir: &Path) -> Result<()> {
+    let journal_dir = dir.join(JOURNAL_DIR);
+    if !journal_dir.exists() {
+        return Ok(());
+    }
+    rollback(dir)
+}
+
+fn rollback(dir: &Path) -> Result<()> {
+    let journal_dir = dir.join(JOURNAL_DIR);
+    let journal_path = journal_dir.join(JOURNAL_FILE);
+
+    // Without a journal, the transaction was never committed, so nothing was moved yet.
+    if journal_path.exists() {
+        let journal_file = File::open(&journal_path).map_err(Error::io(&journal_path))?;
+        let journal: Journal =
+            serde_json::from_reader(journal_file).map_err(Error::json(&journal_path))?;
+        for entry in journal.entries.iter().rev() {
+            let path = dir.join(&entry.file_name);
+            let replaced_path = journal_dir.join(REPLACED_DIR).join(&entry.file_name);
+            let staged_path = journal_dir.join(STAGED_DIR).join(&entry.file_name);
+            if replaced_path.exists() {
+                // The original file was moved aside, put it back.
+                fs::rename(&replaced_path, &path).map_err(Error::io(&path))?;
+            } else if !entry.replaces_existing && !staged_path.exists() && path.exists() {
+                // The file didn't exist before and was moved in, remove it.
+                fs::remove_file(&path).map_err(Error::io(&path))?;
+            }
+        }
+    }
+    fs::remove_dir_all(&journal_dir).map_err(Error::io(&journal_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn write(dir: &Path, file_name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(file_name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn read(dir: &Path, file_name: &str) -> String {
+        fs::read_to_string(dir.join(file_name)).unwrap()
+    }
+
+    #[test]
+    fn commit_moves_staged_files() {
+        let dir = TempDir::new("frangiclave-transaction").unwrap();
+        let sources = TempDir::new("frangiclave-sources").unwrap();
+        write(dir.path(), "a.dll", "old a");
+        let mut transaction = Transaction::begin(dir.path()).unwrap();
+        transaction.stage(&write(sources.path(), "a.dll", "new a")).unwrap();
+        transaction.stage(&write(sources.path(), "b.dll", "new b")).unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(read(dir.path(), "a.dll"), "new a");
+        assert_eq!(read(dir.path(), "b.dll"), "new b");
+        assert!(!dir.path().join(JOURNAL_DIR).exists());
+    }
+
+    #[test]
+    fn commit_rolls_back_on_failure() {
+        let dir = TempDir::new("frangiclave-transaction").unwrap();
+        let sources = TempDir::new("frangiclave-sources").unwrap();
+        write(dir.path(), "a.dll", "old a");
+        let mut transaction = Transaction::begin(dir.path()).unwrap();
+        transaction.stage(&write(sources.path(), "a.dll", "new a")).unwrap();
+        transaction.stage(&write(sources.path(), "b.dll", "new b")).unwrap();
+
+        // A directory appearing where the second file goes makes the commit fail halfway.
+        fs::create_dir_all(dir.path().join("b.dll").join("c")).unwrap();
+        match transaction.commit() {
+            Err(Error::Io { path, .. }) => assert_eq!(path, dir.path().join("b.dll")),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("the commit should have failed"),
+        }
+        assert_eq!(read(dir.path(), "a.dll"), "old a");
+        assert!(dir.path().join("b.dll").join("c").is_dir());
+        assert!(!dir.path().join(JOURNAL_DIR).exists());
+    }
+
+    #[test]
+    fn recover_replays_interrupted_journal() {
+        let dir = TempDir::new("frangiclave-transaction").unwrap();
+        let journal_dir = dir.path().join(JOURNAL_DIR);
+        fs::create_dir_all(journal_dir.join(STAGED_DIR)).unwrap();
+        fs::create_dir_all(journal_dir.join(REPLACED_DIR)).unwrap();
+        let journal = Journal {
+            entries: vec![
+                JournalEntry {
+                    file_name: "a.dll".to_string(),
+                    replaces_existing: true,
+                },
+                JournalEntry {
+                    file_name: "b.dll".to_string(),
+                    replaces_existing: false,
+                },
+                JournalEntry {
+                    file_name: "c.dll".to_string(),
+                    replaces_existing: true,
+                },
+            ],
+        };
+        let journal_file = File::create(journal_dir.join(JOURNAL_FILE)).unwrap();
+        serde_json::to_writer(journal_file, &journal).unwrap();
+
+        // The program stopped after moving a.dll and b.dll, but before moving c.dll aside.
+        write(&journal_dir.join(REPLACED_DIR), "a.dll", "old a");
+        write(dir.path(), "a.dll", "new a");
+        write(dir.path(), "b.dll", "new b");
+        write(&journal_dir.join(STAGED_DIR), "c.dll", "new c");
+        write(dir.path(), "c.dll", "old c");
+
+        recover(dir.path()).unwrap();
+        assert_eq!(read(dir.path(), "a.dll"), "old a");
+        assert!(!dir.path().join("b.dll").exists());
+        assert_eq!(read(dir.path(), "c.dll"), "old c");
+        assert!(!journal_dir.exists());
+    }
+
+    #[test]
+    fn dropping_discards_staged_files() {
+        let dir = TempDir::new("frangiclave-transaction").unwrap();
+        let sources = TempDir::new("frangiclave-sources").unwrap();
+        write(dir.path(), "a.dll", "old a");
+        {
+            let mut transaction = Transaction::begin(dir.path()).unwrap();
+            transaction.stage(&write(sources.path(), "a.dll", "new a")).unwrap();
+        }
+        assert_eq!(read(dir.path(), "a.dll"), "old a");
+        assert!(!dir.path().join(JOURNAL_DIR).exists());
+    }
+}